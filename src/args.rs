@@ -1,13 +1,23 @@
+use {DST_MAC, SRC_NET};
 use clap;
 
 use pnet::datalink::{self, NetworkInterface};
 
+use mock;
+use placement::Placement;
+use size_mix::SizeMix;
+use suites::Protocol;
+use wire;
+
 use rips;
+use rips::ethernet::MacAddr;
 use rips::ipv4::Ipv4Network;
 
-use std::net::{IpAddr, Ipv4Addr, SocketAddrV4};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::path::PathBuf;
 use std::process;
 use std::str::FromStr;
+use std::time::Duration;
 
 
 macro_rules! eprintln {
@@ -17,27 +27,60 @@ macro_rules! eprintln {
     }}
 }
 
+#[derive(Debug)]
+pub enum Size {
+    Min,
+    Mtu,
+    Max,
+    /// An IP packet size in bytes, translated by every suite into its payload
+    /// like the MTU.
+    Bytes(usize),
+}
+
 arg_enum! {
     #[derive(Debug)]
-    pub enum Size {
-        Min,
-        Mtu,
-        Max
+    pub enum Direction {
+        Tx,
+        Rx,
+        Both
     }
 }
 
 arg_enum! {
     #[derive(Debug)]
-    pub enum Protocol {
+    pub enum Output {
+        Human,
+        Json,
+        Quiet
+    }
+}
+
+arg_enum! {
+    #[derive(Debug, PartialEq)]
+    pub enum Backend {
         Pnet,
-        Ethernet,
-        Ipv4,
-        Udp
+        Mock
     }
 }
 
+arg_enum! {
+    #[derive(Debug)]
+    pub enum SinkReceiver {
+        Rips,
+        Pnet
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Mode {
+    Bench,
+    Reflect,
+    Sink,
+}
+
 pub struct ArgumentParser {
     app: clap::App<'static, 'static>,
+    mode: Mode,
     matches: clap::ArgMatches<'static>,
 }
 
@@ -45,12 +88,28 @@ impl ArgumentParser {
     pub fn new() -> ArgumentParser {
         let app = Self::create_app();
         let matches = app.clone().get_matches();
+        let mode = match matches.subcommand_name() {
+            Some("reflect") => Mode::Reflect,
+            Some("sink") => Mode::Sink,
+            _ => Mode::Bench,
+        };
+        // The getters read the arguments of the subcommand, if one is given.
+        let matches = match mode {
+            Mode::Bench => matches,
+            Mode::Reflect => matches.subcommand_matches("reflect").unwrap().clone(),
+            Mode::Sink => matches.subcommand_matches("sink").unwrap().clone(),
+        };
         ArgumentParser {
             app: app,
+            mode: mode,
             matches: matches,
         }
     }
 
+    pub fn get_mode(&self) -> Mode {
+        self.mode
+    }
+
     pub fn get_iface(&self) -> (NetworkInterface, rips::Interface) {
         let iface_name = self.matches.value_of("iface").unwrap();
         for iface in datalink::interfaces().into_iter() {
@@ -65,12 +124,23 @@ impl ArgumentParser {
         self.print_error(&format!("Found no interface named {}", iface_name));
     }
 
+    /// Returns the interface to give to rips. The mock backend accepts any
+    /// interface name.
+    pub fn get_rips_iface(&self) -> rips::Interface {
+        match self.get_backend() {
+            Backend::Pnet => self.get_iface().1,
+            Backend::Mock => mock::interface(self.matches.value_of("iface").unwrap()),
+        }
+    }
+
     pub fn get_src_net(&self) -> Ipv4Network {
         if let Some(src_net) = self.matches.value_of("src_net") {
             match Ipv4Network::from_str(src_net) {
                 Ok(src_net) => src_net,
                 Err(_) => self.print_error("Invalid CIDR"),
             }
+        } else if self.get_backend() == Backend::Mock {
+            *SRC_NET
         } else {
             let (iface, _) = self.get_iface();
             if let Some(ips) = iface.ips.as_ref() {
@@ -108,7 +178,15 @@ impl ArgumentParser {
 
     pub fn get_mtu(&self) -> usize {
         let matches = &self.matches;
-        value_t!(matches, "mtu", usize).unwrap()
+        match value_t!(matches, "mtu", usize) {
+            Ok(mtu) if mtu >= wire::MIN_MTU => mtu,
+            Ok(mtu) => {
+                self.print_error(&format!("MTU {} is below the {} bytes every IPv4 link carries",
+                                          mtu,
+                                          wire::MIN_MTU))
+            }
+            Err(e) => self.print_error(&format!("Invalid MTU. {}", e)),
+        }
     }
 
     pub fn get_netbuf(&self) -> usize {
@@ -116,6 +194,56 @@ impl ArgumentParser {
         value_t!(matches, "netbuf", usize).unwrap()
     }
 
+    pub fn get_duration(&self) -> Duration {
+        self.get_seconds("duration")
+    }
+
+    /// Returns how long the sink should run, or `None` to run until killed.
+    pub fn get_sink_duration(&self) -> Option<Duration> {
+        if self.matches.is_present("duration") {
+            Some(self.get_duration())
+        } else {
+            None
+        }
+    }
+
+    pub fn get_sink_receiver(&self) -> SinkReceiver {
+        let matches = &self.matches;
+        value_t!(matches, "receiver", SinkReceiver).unwrap()
+    }
+
+    pub fn get_control(&self) -> Option<SocketAddr> {
+        let matches = &self.matches;
+        if matches.is_present("control") {
+            match value_t!(matches, "control", SocketAddr) {
+                Ok(addr) => Some(addr),
+                Err(e) => self.print_error(&format!("Invalid control address. {}", e)),
+            }
+        } else {
+            None
+        }
+    }
+
+    pub fn get_warmup(&self) -> Duration {
+        self.get_seconds("warmup")
+    }
+
+    pub fn get_repeat(&self) -> usize {
+        let matches = &self.matches;
+        match value_t!(matches, "repeat", usize) {
+            Ok(repeat) if repeat > 0 => repeat,
+            _ => self.print_error("Invalid number of repetitions"),
+        }
+    }
+
+    pub fn get_threads(&self) -> usize {
+        let matches = &self.matches;
+        match value_t!(matches, "threads", usize) {
+            Ok(threads) if threads > 0 => threads,
+            _ => self.print_error("Invalid number of threads"),
+        }
+    }
+
     pub fn get_dst(&self) -> SocketAddrV4 {
         let matches = &self.matches;
         match value_t!(matches, "target", SocketAddrV4) {
@@ -124,9 +252,96 @@ impl ArgumentParser {
         }
     }
 
+    /// Returns the destination MAC for raw Ethernet frames, or `None` if it
+    /// should be resolved with ARP.
+    pub fn get_dst_mac(&self) -> Option<MacAddr> {
+        match self.matches.value_of("dst_mac") {
+            None => Some(*DST_MAC),
+            Some("auto") => None,
+            Some(mac_str) => {
+                match MacAddr::from_str(mac_str) {
+                    Ok(mac) => Some(mac),
+                    Err(_) => self.print_error("Unable to parse destination MAC"),
+                }
+            }
+        }
+    }
+
+    pub fn get_placement(&self) -> Placement {
+        let mut placement = Placement::new();
+        if let Some(cpus) = self.matches.values_of("cpu") {
+            for cpus in cpus {
+                match parse_cpu_range(cpus) {
+                    Some(range) => placement.cpus.extend(range),
+                    None => self.print_error(&format!("Invalid CPU list entry: {}", cpus)),
+                }
+            }
+        }
+        if let Some(sched) = self.matches.value_of("sched") {
+            let mut parts = sched.splitn(2, ':');
+            match (parts.next(), parts.next().map(i32::from_str)) {
+                (Some("fifo"), Some(Ok(priority))) => placement.fifo_priority = Some(priority),
+                (Some("other"), None) => (),
+                _ => self.print_error(&format!("Invalid scheduling policy: {}", sched)),
+            }
+        }
+        placement
+    }
+
+    pub fn get_stamp(&self) -> bool {
+        self.matches.is_present("stamp")
+    }
+
+    pub fn get_send_latency(&self) -> bool {
+        self.matches.is_present("send_latency")
+    }
+
     pub fn get_sizes(&self) -> Vec<Size> {
-        let matches = &self.matches;
-        values_t!(matches, "sizes", Size).unwrap()
+        let mut sizes = Vec::new();
+        if let Some(values) = self.matches.values_of("sizes") {
+            for size in values {
+                sizes.push(match size.to_lowercase().as_str() {
+                    "min" => Size::Min,
+                    "mtu" => Size::Mtu,
+                    "max" => Size::Max,
+                    bytes => {
+                        match usize::from_str(bytes) {
+                            Ok(bytes) => Size::Bytes(bytes),
+                            Err(_) => self.print_error(&format!("Invalid size: {}", size)),
+                        }
+                    }
+                });
+            }
+        }
+        if let Some(sweep) = self.matches.value_of("size_sweep") {
+            match parse_size_sweep(sweep) {
+                Some(sweep) => sizes.extend(sweep.into_iter().map(Size::Bytes)),
+                None => self.print_error(&format!("Invalid size sweep: {}", sweep)),
+            }
+        }
+        sizes
+    }
+
+    /// Returns the packets per send call to bench, or an empty list to use
+    /// the defaults of every suite.
+    pub fn get_batches(&self) -> Vec<usize> {
+        let mut batches = Vec::new();
+        if let Some(values) = self.matches.values_of("batch") {
+            for batch in values {
+                match usize::from_str(batch) {
+                    Ok(batch) if batch > 0 => batches.push(batch),
+                    _ => self.print_error(&format!("Invalid batch size: {}", batch)),
+                }
+            }
+        }
+        batches
+    }
+
+    pub fn get_size_mix(&self) -> Option<SizeMix> {
+        self.matches.value_of("size_mix").map(|mix| match parse_size_mix(mix) {
+            Some(mix) => mix,
+            None => self.print_error(&format!("Invalid size mix: {}", mix)),
+        })
     }
 
     pub fn get_protocols(&self) -> Vec<Protocol> {
@@ -134,12 +349,46 @@ impl ArgumentParser {
         values_t!(matches, "protocols", Protocol).unwrap()
     }
 
-    pub fn create_channel(&self) -> rips::EthernetChannel {
+    pub fn get_direction(&self) -> Direction {
+        let matches = &self.matches;
+        value_t!(matches, "direction", Direction).unwrap()
+    }
+
+    pub fn get_output(&self) -> Output {
+        let matches = &self.matches;
+        value_t!(matches, "output", Output).unwrap()
+    }
+
+    pub fn get_results_path(&self) -> Option<PathBuf> {
+        self.matches.value_of("results").map(PathBuf::from)
+    }
+
+    pub fn get_timeseries_path(&self) -> Option<PathBuf> {
+        self.matches.value_of("timeseries").map(PathBuf::from)
+    }
+
+    pub fn get_backend(&self) -> Backend {
+        let matches = &self.matches;
+        value_t!(matches, "backend", Backend).unwrap()
+    }
+
+    pub fn get_mock_capture(&self) -> usize {
+        let matches = &self.matches;
+        match value_t!(matches, "mock_capture", usize) {
+            Ok(capture) => capture,
+            Err(e) => self.print_error(&format!("Invalid number of frames to capture. {}", e)),
+        }
+    }
+
+    /// Opens a datalink channel on the interface. Reads time out after
+    /// `read_timeout`, if given.
+    pub fn create_channel(&self, read_timeout: Option<Duration>) -> rips::EthernetChannel {
         let bufsize = self.get_netbuf();
         let (iface, _) = self.get_iface();
         let mut config = datalink::Config::default();
         config.write_buffer_size = bufsize;
         config.read_buffer_size = bufsize;
+        config.read_timeout = read_timeout;
         match datalink::channel(&iface, config) {
             Ok(datalink::Channel::Ethernet(tx, rx)) => {
                 rips::EthernetChannel {
@@ -153,6 +402,14 @@ impl ArgumentParser {
         }
     }
 
+    fn get_seconds(&self, name: &str) -> Duration {
+        let matches = &self.matches;
+        match value_t!(matches, name, u64) {
+            Ok(secs) => Duration::new(secs, 0),
+            Err(e) => self.print_error(&format!("Invalid {}. {}", name, e)),
+        }
+    }
+
     fn create_app() -> clap::App<'static, 'static> {
         let src_net_arg = clap::Arg::with_name("src_net")
             .long("ip")
@@ -176,13 +433,61 @@ impl ArgumentParser {
         let mtu_arg = clap::Arg::with_name("mtu")
             .long("mtu")
             .value_name("MTU")
-            .help("Maximum transmission unit (MTU) for the transmission.")
+            .help("Maximum transmission unit (MTU) for the transmission. At least 68.")
             .default_value("1500");
         let netbuf_arg = clap::Arg::with_name("netbuf")
             .long("netbuf")
             .value_name("SIZE")
             .help("Number of bytes allocated for the network TX/RX buffers.")
             .default_value("65535");
+        let duration_arg = clap::Arg::with_name("duration")
+            .long("duration")
+            .value_name("SECS")
+            .help("Number of seconds to measure each benchmark case.")
+            .default_value("10");
+        let warmup_arg = clap::Arg::with_name("warmup")
+            .long("warmup")
+            .value_name("SECS")
+            .help("Number of seconds to run each benchmark case before starting to measure.")
+            .default_value("0");
+        let repeat_arg = clap::Arg::with_name("repeat")
+            .long("repeat")
+            .value_name("N")
+            .help("Number of times to measure each benchmark case.")
+            .default_value("1");
+        let threads_arg = clap::Arg::with_name("threads")
+            .long("threads")
+            .value_name("N")
+            .help("Number of threads sending concurrently in the rips Ethernet, IPv4 and UDP \
+                   suites, their construction suites and the kernel suite. The rips suites \
+                   share one network stack between all threads. The pnet suite has a single \
+                   sender and always sends from one thread.")
+            .default_value("1");
+        let cpu_arg = clap::Arg::with_name("cpu")
+            .long("cpu")
+            .value_name("LIST")
+            .help("CPUs to pin the benchmark threads to, given as a list like 0,2,4-7. Thread N \
+                   is pinned to the Nth CPU in the list, wrapping around.")
+            .value_delimiter(",")
+            .multiple(true);
+        let sched_arg = clap::Arg::with_name("sched")
+            .long("sched")
+            .value_name("POLICY")
+            .help("Scheduling policy of the benchmark threads, either other or fifo:<prio> for \
+                   realtime SCHED_FIFO with the given priority.")
+            .takes_value(true);
+        let backend_arg = clap::Arg::with_name("backend")
+            .long("backend")
+            .value_name("BACKEND")
+            .help("How to send and receive frames. The mock backend never touches the network, \
+                   it only counts the frames and answers ARP requests.")
+            .possible_values(&Backend::variants())
+            .default_value("Pnet");
+        let mock_capture_arg = clap::Arg::with_name("mock_capture")
+            .long("mock-capture")
+            .value_name("N")
+            .help("Number of frames the mock backend keeps and prints after each suite.")
+            .default_value("0");
         let iface_arg = clap::Arg::with_name("iface")
             .help("Network interface to use")
             .required(true)
@@ -191,34 +496,170 @@ impl ArgumentParser {
             .help("Target to connect to. Given as <ip>:<port>")
             .required(true)
             .index(2);
+        let dst_mac_arg = clap::Arg::with_name("dst_mac")
+            .long("dst-mac")
+            .value_name("MAC")
+            .help("Destination MAC for raw Ethernet frames. Use \"auto\" to resolve the MAC of \
+                   the target, or the gateway if the target is not on the local network, with \
+                   ARP before the run.")
+            .takes_value(true);
         let sizes_arg = clap::Arg::with_name("sizes")
-            .help("Configure which package sizes to bench")
+            .help("Configure which package sizes to bench. Either min, mtu, max or IP packet \
+                   sizes in bytes, like 64,576,1500. Every suite sends the payload making up \
+                   packets of that size. Defaults to mtu.")
             .long("sizes")
             .use_delimiter(true)
             .multiple(true)
             .value_delimiter(",")
-            .possible_values(&Size::variants())
             .takes_value(true);
+        let size_sweep_arg = clap::Arg::with_name("size_sweep")
+            .long("size-sweep")
+            .value_name("RANGE")
+            .help("Bench IP packet sizes from FIRST up to and including LAST, given as \
+                   FIRST..LAST:step=STEP, like 64..1500:step=64. Added to --sizes.")
+            .takes_value(true);
+        let size_mix_arg = clap::Arg::with_name("size_mix")
+            .long("size-mix")
+            .value_name("MIX")
+            .help("Send a mix of IP packet sizes instead of one size per case, in the suites \
+                   able to. Either imix, for 64, 576 and 1500 bytes in a 7:4:1 ratio, or a \
+                   weighted list like 64:7,576:4,1500:1.")
+            .takes_value(true);
+        let batch_arg = clap::Arg::with_name("batch")
+            .long("batch")
+            .value_name("LIST")
+            .help("Packets per send call to bench, like 1,10,100,1000. Used by the suites able \
+                   to send batches, the others send one packet per call.")
+            .value_delimiter(",")
+            .multiple(true);
         let protocols_arg = clap::Arg::with_name("protocols")
             .help("What benchmark suites to run")
             .long("protocols")
             .value_delimiter(",")
             .multiple(true)
             .possible_values(&Protocol::variants());
+        let direction_arg = clap::Arg::with_name("direction")
+            .help("Benchmark the transmit path, the receive path or both")
+            .long("direction")
+            .value_name("DIRECTION")
+            .possible_values(&Direction::variants())
+            .default_value("Tx");
+        let output_arg = clap::Arg::with_name("output")
+            .help("Format of the results written to stdout. Progress is written to stderr \
+                   when the results are json. Quiet writes nothing, the results can still be \
+                   written to a file with --results")
+            .long("output")
+            .value_name("FORMAT")
+            .possible_values(&Output::variants())
+            .default_value("Human");
+        let results_arg = clap::Arg::with_name("results")
+            .help("File to write one json record per benchmark case to")
+            .long("results")
+            .value_name("PATH")
+            .takes_value(true);
+        let timeseries_arg = clap::Arg::with_name("timeseries")
+            .help("CSV file to record every per-second sample of every benchmark case to")
+            .long("timeseries")
+            .value_name("PATH")
+            .takes_value(true);
+        let stamp_arg = clap::Arg::with_name("stamp")
+            .help("Stamp every IPv4 and UDP payload with a sequence number and the time it was \
+                   sent, for the sink to detect loss and reordering. Payloads shorter than 28 \
+                   bytes are not stamped")
+            .long("stamp");
+        let send_latency_arg = clap::Arg::with_name("send_latency")
+            .help("Time every send call and report the distribution of the call durations. \
+                   Costs two clock reads per call, so it lowers the measured rate")
+            .long("send-latency");
+        let sink_duration_arg = clap::Arg::with_name("duration")
+            .long("duration")
+            .value_name("SECS")
+            .help("Number of seconds to receive. Runs until killed if not given.")
+            .takes_value(true);
+        let control_arg = clap::Arg::with_name("control")
+            .long("control")
+            .value_name("ADDR")
+            .help("Control address of a sink, given as <ip>:<port>. Every case is announced to \
+                   and measured in lockstep with the sink, and the delivered counts are added \
+                   to the results.")
+            .takes_value(true);
+        let sink_control_arg = clap::Arg::with_name("control")
+            .long("control")
+            .value_name("ADDR")
+            .help("Address to accept a control connection from a sender on, given as \
+                   <ip>:<port>. Uses the kernel network stack.")
+            .takes_value(true);
+        let sink_receiver_arg = clap::Arg::with_name("receiver")
+            .long("receiver")
+            .value_name("RECEIVER")
+            .help("Receive through a rips UDP socket, or count the matching frames read directly \
+                   from the pnet datalink channel.")
+            .possible_values(&SinkReceiver::variants())
+            .default_value("Rips");
+
+        let reflect_cmd = clap::SubCommand::with_name("reflect")
+            .about("Echo every UDP datagram received on the local address back to its sender. \
+                    Used as the far end of the UdpRtt suite.")
+            .arg(src_net_arg.clone())
+            .arg(src_port_arg.clone())
+            .arg(gw_arg.clone())
+            .arg(netbuf_arg.clone())
+            .arg(backend_arg.clone())
+            .arg(mock_capture_arg.clone())
+            .arg(iface_arg.clone());
+        let sink_cmd = clap::SubCommand::with_name("sink")
+            .about("Receive UDP datagrams on the local address and report the delivered rate. \
+                    Datagrams sent with --stamp are checked for loss, duplication and \
+                    reordering.")
+            .arg(src_net_arg.clone())
+            .arg(src_port_arg.clone())
+            .arg(gw_arg.clone())
+            .arg(netbuf_arg.clone())
+            .arg(backend_arg.clone())
+            .arg(mock_capture_arg.clone())
+            .arg(sink_duration_arg)
+            .arg(sink_receiver_arg)
+            .arg(sink_control_arg)
+            .arg(output_arg.clone())
+            .arg(results_arg.clone())
+            .arg(timeseries_arg.clone())
+            .arg(iface_arg.clone());
 
         let app = clap::App::new(crate_name!())
             .version(crate_version!())
             .author(crate_authors!())
             .about(crate_description!())
+            .setting(clap::AppSettings::SubcommandsNegateReqs)
+            .subcommand(reflect_cmd)
+            .subcommand(sink_cmd)
             .arg(src_net_arg)
             .arg(src_port_arg)
             .arg(gw_arg)
             .arg(mtu_arg)
             .arg(netbuf_arg)
+            .arg(duration_arg)
+            .arg(warmup_arg)
+            .arg(repeat_arg)
+            .arg(threads_arg)
+            .arg(cpu_arg)
+            .arg(sched_arg)
+            .arg(backend_arg)
+            .arg(mock_capture_arg)
             .arg(iface_arg)
             .arg(dst_arg)
+            .arg(dst_mac_arg)
             .arg(sizes_arg)
-            .arg(protocols_arg);
+            .arg(size_sweep_arg)
+            .arg(size_mix_arg)
+            .arg(batch_arg)
+            .arg(protocols_arg)
+            .arg(direction_arg)
+            .arg(output_arg)
+            .arg(results_arg)
+            .arg(timeseries_arg)
+            .arg(stamp_arg)
+            .arg(send_latency_arg)
+            .arg(control_arg);
 
         app
     }
@@ -230,3 +671,93 @@ impl ArgumentParser {
         process::exit(1);
     }
 }
+
+/// Parses a single CPU number or an inclusive range like `4-7`.
+fn parse_cpu_range(cpus: &str) -> Option<Vec<usize>> {
+    let mut bounds = cpus.splitn(2, '-').map(usize::from_str);
+    match (bounds.next(), bounds.next()) {
+        (Some(Ok(cpu)), None) => Some(vec![cpu]),
+        (Some(Ok(first)), Some(Ok(last))) if first <= last => Some((first..last + 1).collect()),
+        _ => None,
+    }
+}
+
+/// Parses a size sweep like `64..1500:step=64`. The step is optional and
+/// defaults to one.
+fn parse_size_sweep(sweep: &str) -> Option<Vec<usize>> {
+    let mut parts = sweep.splitn(2, ':');
+    let range = parts.next().unwrap();
+    let step = match parts.next() {
+        Some(step) if step.starts_with("step=") => usize::from_str(&step[5..]).ok(),
+        Some(_) => None,
+        None => Some(1),
+    };
+    let mut bounds = range.splitn(2, "..").map(usize::from_str);
+    match (bounds.next(), bounds.next(), step) {
+        (Some(Ok(first)), Some(Ok(last)), Some(step)) if first <= last && step > 0 => {
+            Some((first..last + 1).filter(|size| (size - first) % step == 0).collect())
+        }
+        _ => None,
+    }
+}
+
+/// Parses `imix` or a list of `size:weight` pairs like `64:7,576:4,1500:1`.
+fn parse_size_mix(mix: &str) -> Option<SizeMix> {
+    if mix.to_lowercase() == "imix" {
+        return Some(SizeMix::imix());
+    }
+    let mut entries = Vec::new();
+    for entry in mix.split(',') {
+        let mut parts = entry.splitn(2, ':');
+        match (parts.next().map(usize::from_str), parts.next().map(u32::from_str)) {
+            (Some(Ok(size)), Some(Ok(weight))) => entries.push((size, weight)),
+            _ => return None,
+        }
+    }
+    SizeMix::new(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_size_mix, parse_size_sweep};
+
+    #[test]
+    fn size_sweep_includes_last() {
+        assert_eq!(parse_size_sweep("64..68"), Some(vec![64, 65, 66, 67, 68]));
+        assert_eq!(parse_size_sweep("64..64"), Some(vec![64]));
+    }
+
+    #[test]
+    fn size_sweep_steps() {
+        assert_eq!(parse_size_sweep("64..1500:step=512"), Some(vec![64, 576, 1088]));
+        assert_eq!(parse_size_sweep("0..1024:step=512"), Some(vec![0, 512, 1024]));
+    }
+
+    #[test]
+    fn size_sweep_rejects_bad_input() {
+        for sweep in &["", "64", "64..", "..64", "100..64", "a..b", "64..128:step=0",
+                       "64..128:step=x", "64..128:by=2"] {
+            assert_eq!(parse_size_sweep(sweep), None, "{}", sweep);
+        }
+    }
+
+    #[test]
+    fn size_mix_parses_pairs() {
+        let mix = parse_size_mix("64:7,576:4,1500:1").unwrap();
+        assert_eq!(mix.to_string(), "64:7,576:4,1500:1");
+        assert_eq!(parse_size_mix("IMIX").unwrap().to_string(), "64:7,576:4,1500:1");
+    }
+
+    #[test]
+    fn size_mix_rejects_bad_input() {
+        for mix in &["", "64", "64:", "64:7,", "a:1", "64:-1", "64:7;576:4"] {
+            assert!(parse_size_mix(mix).is_none(), "{}", mix);
+        }
+    }
+
+    #[test]
+    fn size_mix_rejects_zero_weights() {
+        assert!(parse_size_mix("64:0,1500:0").is_none());
+        assert!(parse_size_mix("64:0,1500:1").is_some());
+    }
+}
@@ -0,0 +1,214 @@
+//! The `Benchmark` trait implemented by all throughput suites and the runner
+//! taking care of measuring them.
+
+use Config;
+use progress::{Meter, Printer};
+use rips::EthernetChannel;
+use threads;
+use wire::Encapsulation;
+
+use std::io;
+use std::time::Instant;
+
+/// Does one send call of the given packets per call and bytes per packet.
+/// Returns the number of packets and bytes sent, or `None` if the Tx was
+/// invalidated by a change in the stack and nothing was sent.
+pub type SendFn = Box<FnMut(usize, usize) -> io::Result<Option<(usize, usize)>> + Send>;
+
+/// A suite measuring how fast something sends. Implementors only set up
+/// what to send with. Warmup, repetitions, threads, statistics and errors
+/// are handled by `run`.
+pub trait Benchmark {
+    /// Prepares for sending over `channel`, like creating a network stack.
+    fn setup(channel: EthernetChannel,
+             config: &Config,
+             printer: &mut Printer)
+             -> io::Result<Self>
+        where Self: Sized;
+
+    fn title(&self) -> &'static str;
+
+    /// What the bytes counted by the senders are the payload of.
+    fn encapsulation(&self) -> Encapsulation;
+
+    /// Whether the suite is meant to send into a mock channel discarding
+    /// every frame, measuring only the work done before the frames leave.
+    fn discards(&self) -> bool {
+        false
+    }
+
+    /// Whether the senders draw the size of every packet from
+    /// `config.size_mix` when it's set, ignoring the bytes per packet.
+    fn mixes_sizes(&self) -> bool {
+        false
+    }
+
+    /// Packets per send call of every case. Suites able to send batches
+    /// should use `batches`.
+    fn batches(&self, _config: &Config) -> Vec<usize> {
+        vec![1]
+    }
+
+    /// Bytes per packet of every case. Sizes left out are reported on
+    /// `printer`.
+    fn sizes(&self, config: &Config, printer: &mut Printer) -> Vec<usize>;
+
+    /// Most bytes a single send call can take, if limited. Cases sending
+    /// more per call are skipped.
+    fn max_bytes_per_call(&self) -> Option<usize> {
+        None
+    }
+
+    /// Creates the sender for thread number `thread`, counting from zero.
+    /// Returns `None` if the suite can't send from that many threads, and an
+    /// error if the sender can't be set up.
+    fn sender(&mut self, config: &Config, thread: usize) -> io::Result<Option<SendFn>>;
+
+    /// Called when all cases are done.
+    fn teardown(&mut self, _printer: &mut Printer) {}
+}
+
+/// Sets up the benchmark `B` and runs all its cases. Stops at the first
+/// error, like a failed send call.
+pub fn run<B: Benchmark>(channel: EthernetChannel,
+                         config: &Config,
+                         printer: &mut Printer)
+                         -> io::Result<()> {
+    let mut benchmark = B::setup(channel, config, printer)?;
+    printer.print_title(benchmark.title());
+    printer.set_encapsulation(benchmark.encapsulation());
+    if benchmark.discards() {
+        printer.set_discarding();
+    }
+
+    let mut senders = Vec::new();
+    while senders.len() < config.threads {
+        match benchmark.sender(config, senders.len())? {
+            Some(sender) => senders.push(sender),
+            None => break,
+        }
+    }
+    if senders.len() < config.threads {
+        printer.print_message(&format!("{} can only send from {} thread(s)",
+                                       benchmark.title(),
+                                       senders.len()));
+    }
+
+    let size_mix = if benchmark.mixes_sizes() {
+        config.size_mix.as_ref()
+    } else {
+        if config.size_mix.is_some() {
+            printer.print_message(&format!("{} can't mix sizes, using fixed sizes",
+                                           benchmark.title()));
+        }
+        None
+    };
+    // A mix is sent as a single case per batch size, with no fixed size.
+    let sizes = match size_mix {
+        Some(_) => vec![0],
+        None => benchmark.sizes(config, printer),
+    };
+
+    let batches = benchmark.batches(config);
+    if !config.batches.is_empty() && batches != config.batches {
+        printer.print_message(&format!("{} can't send batches, sending one packet per call",
+                                       benchmark.title()));
+    }
+
+    let mut total_invalid_tx = 0;
+    for packets_per_call in batches {
+        for &bytes_per_packet in &sizes {
+            let bytes_per_call = packets_per_call * bytes_per_packet;
+            match benchmark.max_bytes_per_call() {
+                Some(max) if bytes_per_call > max => {
+                    let text = format!("Skipping {}x{} bytes, more than {} bytes per send call",
+                                       packets_per_call,
+                                       bytes_per_packet,
+                                       max);
+                    printer.print_message(&text);
+                    continue;
+                }
+                _ => (),
+            }
+            let desc = match (size_mix, packets_per_call) {
+                (Some(mix), 1) => format!("Sending size mix {}", mix),
+                (Some(mix), _) => format!("Sending {}x size mix {}", packets_per_call, mix),
+                (None, 1) => format!("Sending {} bytes per packet", bytes_per_packet),
+                (None, _) => format!("Sending {}x{} bytes", packets_per_call, bytes_per_packet),
+            };
+            printer.start_case(&desc, packets_per_call, bytes_per_packet)?;
+            if size_mix.is_some() {
+                printer.set_mixed_sizes();
+            }
+            // With several threads asked for, even a single sender runs on a
+            // thread of its own, placed like the others.
+            if config.threads > 1 {
+                let (returned_senders, invalid_tx) = threads::measure(printer,
+                                                                      config,
+                                                                      senders,
+                                                                      packets_per_call,
+                                                                      bytes_per_packet)?;
+                senders = returned_senders;
+                total_invalid_tx += invalid_tx;
+            } else {
+                total_invalid_tx +=
+                    measure(printer, config, &mut senders[0], packets_per_call, bytes_per_packet)?;
+            }
+            printer.end_case();
+        }
+    }
+    if total_invalid_tx > 0 {
+        printer.print_message(&format!("Benchmark resulted in {} InvalidTx", total_invalid_tx));
+    }
+    benchmark.teardown(printer);
+    Ok(())
+}
+
+/// The packets per send call of a suite able to send batches. Those given
+/// in the config, or `default`.
+pub fn batches(config: &Config, default: &[usize]) -> Vec<usize> {
+    if config.batches.is_empty() {
+        default.to_vec()
+    } else {
+        config.batches.clone()
+    }
+}
+
+/// Runs all repetitions of one case with a single sender on the current
+/// thread. Returns the number of invalidated Tx.
+fn measure(printer: &mut Printer,
+           config: &Config,
+           send: &mut SendFn,
+           packets_per_call: usize,
+           bytes_per_packet: usize)
+           -> io::Result<usize> {
+    let mut total_invalid_tx = 0;
+    for _ in 0..config.repeat {
+        let mut meter = Meter::new(config);
+        let mut invalid_tx = 0;
+        while meter.tick(printer)? {
+            let result = if config.send_latency {
+                let start = Instant::now();
+                let result = send(packets_per_call, bytes_per_packet);
+                meter.add_send_latency(start.elapsed());
+                result
+            } else {
+                send(packets_per_call, bytes_per_packet)
+            };
+            match result {
+                Ok(Some((pkgs, bytes))) => meter.add(pkgs, bytes),
+                Ok(None) => invalid_tx += 1,
+                Err(e) => return Err(context(e, "Error while sending to the network")),
+            }
+        }
+        total_invalid_tx += invalid_tx;
+        printer.set_invalid_tx(invalid_tx);
+        printer.end_repetition();
+    }
+    Ok(total_invalid_tx)
+}
+
+/// Prefixes the message of `e` with what was being done when it happened.
+pub fn context(e: io::Error, what: &str) -> io::Error {
+    io::Error::new(e.kind(), format!("{}: {}", what, e))
+}
@@ -0,0 +1,137 @@
+//! Measures the pnet checksum functions rips relies on, to compare checksum
+//! implementations between pnet versions. Nothing is sent.
+
+use Config;
+use pnet::packet::ipv4::{self, Ipv4Packet};
+use pnet::packet::udp::{self, UdpPacket};
+use progress::{Meter, Printer};
+use results::JsonObject;
+use rips::EthernetChannel;
+use rips_bench::{self, Protocol};
+use std::io;
+use std::ptr;
+
+const IPV4_HEADER: usize = 20;
+const UDP_HEADER: usize = 8;
+
+/// Offsets from eight byte alignment the checksummed data is placed at.
+const ALIGNMENTS: [usize; 4] = [0, 1, 2, 4];
+
+/// Checksums computed between every check of the timer, so the clock is not
+/// what gets measured for small packets.
+const CHECKSUMS_PER_TICK: usize = 64;
+
+const TITLE: &'static str = "Pnet checksums";
+
+/// Not a `Benchmark`, as the cases vary in alignment rather than in packets
+/// per call, but what it can't do is reported the same way `bench::run` does.
+pub fn bench(_channel: EthernetChannel, config: &Config, printer: &mut Printer) -> io::Result<()> {
+    printer.print_title(TITLE);
+    printer.set_discarding();
+    if config.threads > 1 {
+        printer.print_message(&format!("{} can only run on 1 thread", TITLE));
+    }
+    if config.size_mix.is_some() {
+        printer.print_message(&format!("{} can't mix sizes, using fixed sizes", TITLE));
+    }
+    if !config.batches.is_empty() && config.batches != [1] {
+        printer.print_message(&format!("{} can't batch, computing one checksum per call",
+                                       TITLE));
+    }
+    let src = config.src_net.ip();
+    let dst = *config.dst.ip();
+
+    // Version 4 and a header of five words, the identification is varied.
+    let ipv4_header = [0x45, 0, 0, IPV4_HEADER as u8];
+    for &alignment in ALIGNMENTS.iter() {
+        let desc = format!("IPv4 header checksum at offset {}", alignment);
+        let case = Case {
+            kind: "ipv4",
+            size: IPV4_HEADER,
+            alignment: alignment,
+            header: &ipv4_header,
+            varied: 4,
+        };
+        measure(printer, config, &desc, case, |data| {
+            ipv4::checksum(&Ipv4Packet::new(data).unwrap())
+        })?;
+    }
+    for bytes_per_packet in rips_bench::packet_sizes(config, Protocol::Udp, printer) {
+        for &alignment in ALIGNMENTS.iter() {
+            let desc = format!("UDP checksum of {} bytes at offset {}",
+                               bytes_per_packet,
+                               alignment);
+            let size = UDP_HEADER + bytes_per_packet;
+            // The length is set, the source port is varied.
+            let udp_header = [0, 0, 0, 0, (size >> 8) as u8, size as u8];
+            let case = Case {
+                kind: "udp",
+                size: size,
+                alignment: alignment,
+                header: &udp_header,
+                varied: 0,
+            };
+            measure(printer, config, &desc, case, |data| {
+                udp::ipv4_checksum(&UdpPacket::new(data).unwrap(), src, dst)
+            })?;
+        }
+    }
+    Ok(())
+}
+
+struct Case<'a> {
+    kind: &'static str,
+    /// Bytes covered by the checksum.
+    size: usize,
+    /// Offset from an eight byte boundary the data is placed at.
+    alignment: usize,
+    /// Start of the data, the rest is zeroes.
+    header: &'a [u8],
+    /// Offset of the two bytes changed between every checksum, so it can't
+    /// be computed once and reused.
+    varied: usize,
+}
+
+/// Runs all repetitions of one case and prints the throughput in GB/s.
+fn measure<F>(printer: &mut Printer,
+              config: &Config,
+              desc: &str,
+              case: Case,
+              mut checksum: F)
+              -> io::Result<()>
+    where F: FnMut(&[u8]) -> u16
+{
+    let size = case.size;
+    let mut buffer = vec![0u8; size + 2 * 8];
+    let offset = (8 - buffer.as_ptr() as usize % 8) % 8 + case.alignment;
+    buffer[offset..offset + case.header.len()].copy_from_slice(case.header);
+    let varied = offset + case.varied;
+    let mut folded = 0u16;
+
+    printer.start_case(desc, 1, size)?;
+    printer.add_case_details("checksum",
+                             JsonObject::new()
+                                 .string("kind", case.kind)
+                                 .number("alignment", case.alignment));
+    for _ in 0..config.repeat {
+        let mut meter = Meter::new(config);
+        let mut iteration = 0usize;
+        while meter.tick(printer)? {
+            for _ in 0..CHECKSUMS_PER_TICK {
+                iteration = iteration.wrapping_add(1);
+                buffer[varied] = iteration as u8;
+                buffer[varied + 1] = (iteration >> 8) as u8;
+                folded ^= checksum(&buffer[offset..offset + size]);
+            }
+            meter.add(CHECKSUMS_PER_TICK, CHECKSUMS_PER_TICK * size);
+        }
+        printer.end_repetition();
+    }
+    printer.end_case();
+    // Keeps the checksums from being optimized away.
+    unsafe { ptr::write_volatile(&mut folded, folded) };
+
+    let bytes_per_sec = printer.results().last().unwrap().bytes_per_sec();
+    printer.print_message(&format!("{}: {:.3} GB/s", desc, bytes_per_sec / 1_000_000_000f64));
+    Ok(())
+}
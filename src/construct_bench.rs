@@ -0,0 +1,64 @@
+//! Suites measuring how long rips takes to build packets, isolated from the
+//! kernel. They send like the rips suites, but are always given a mock
+//! channel that discards every frame without touching a socket. Comparing
+//! the time per packet between the layers shows what every layer adds.
+
+use Config;
+use bench::{Benchmark, SendFn};
+use progress::Printer;
+use rips::EthernetChannel;
+use rips_bench::{EthernetBench, Ipv4Bench, UdpBench};
+use wire::Encapsulation;
+
+use std::io;
+
+macro_rules! construct_bench {
+    ($name:ident, $inner:ident, $title:expr) => {
+        pub struct $name($inner);
+
+        impl Benchmark for $name {
+            fn setup(channel: EthernetChannel,
+                     config: &Config,
+                     printer: &mut Printer)
+                     -> io::Result<Self> {
+                $inner::setup(channel, config, printer).map($name)
+            }
+
+            fn title(&self) -> &'static str {
+                $title
+            }
+
+            fn encapsulation(&self) -> Encapsulation {
+                self.0.encapsulation()
+            }
+
+            fn discards(&self) -> bool {
+                true
+            }
+
+            fn mixes_sizes(&self) -> bool {
+                self.0.mixes_sizes()
+            }
+
+            fn batches(&self, config: &Config) -> Vec<usize> {
+                self.0.batches(config)
+            }
+
+            fn sizes(&self, config: &Config, printer: &mut Printer) -> Vec<usize> {
+                self.0.sizes(config, printer)
+            }
+
+            fn max_bytes_per_call(&self) -> Option<usize> {
+                self.0.max_bytes_per_call()
+            }
+
+            fn sender(&mut self, config: &Config, thread: usize) -> io::Result<Option<SendFn>> {
+                self.0.sender(config, thread)
+            }
+        }
+    }
+}
+
+construct_bench!(EthernetConstructBench, EthernetBench, "Rips Ethernet construction");
+construct_bench!(Ipv4ConstructBench, Ipv4Bench, "Rips IPv4 construction");
+construct_bench!(UdpConstructBench, UdpBench, "Rips UDP construction");
@@ -0,0 +1,145 @@
+//! Line based control protocol keeping a sender and a sink in lockstep.
+//!
+//! The sender announces every case with `CASE <packets_per_call>
+//! <bytes_per_packet> <suite>`, and brackets every measurement with `START`
+//! and `STOP`. The sink answers `OK` to everything except `STOP`, which it
+//! answers with `RESULT <packets> <bytes>` delivered since `START`.
+
+use rx::RxCounter;
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::Sender;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Sender side of the control connection.
+pub struct ControlClient {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl ControlClient {
+    pub fn connect(addr: SocketAddr) -> io::Result<Self> {
+        let writer = TcpStream::connect(addr)?;
+        writer.set_nodelay(true)?;
+        let reader = BufReader::new(writer.try_clone()?);
+        Ok(ControlClient {
+            reader: reader,
+            writer: writer,
+        })
+    }
+
+    pub fn announce(&mut self,
+                    suite: &str,
+                    packets_per_call: usize,
+                    bytes_per_packet: usize)
+                    -> io::Result<()> {
+        let request = format!("CASE {} {} {}", packets_per_call, bytes_per_packet, suite);
+        self.request(&request).and_then(expect_ok)
+    }
+
+    pub fn start(&mut self) -> io::Result<()> {
+        self.request("START").and_then(expect_ok)
+    }
+
+    /// Stops the measurement and returns the number of packets and bytes the
+    /// sink got since it was started.
+    pub fn stop(&mut self) -> io::Result<(usize, usize)> {
+        let response = self.request("STOP")?;
+        let mut words = response.split_whitespace();
+        if words.next() == Some("RESULT") {
+            let packets = words.next().and_then(|w| w.parse().ok());
+            let bytes = words.next().and_then(|w| w.parse().ok());
+            if let (Some(packets), Some(bytes)) = (packets, bytes) {
+                return Ok((packets, bytes));
+            }
+        }
+        Err(invalid_response(&response))
+    }
+
+    fn request(&mut self, request: &str) -> io::Result<String> {
+        writeln!(self.writer, "{}", request)?;
+        let mut response = String::new();
+        if self.reader.read_line(&mut response)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Sink closed connection"));
+        }
+        Ok(response.trim().to_owned())
+    }
+}
+
+/// What the sink is told over the control connection.
+pub enum Event {
+    Case(String),
+    Result {
+        packets: usize,
+        bytes: usize,
+        duration: Duration,
+    },
+    Closed,
+}
+
+/// Listens for a sender on `addr` and serves it from a separate thread,
+/// reporting the packets counted by `counter` between every `START` and
+/// `STOP`.
+pub fn spawn_server(addr: SocketAddr,
+                    counter: RxCounter,
+                    events: Sender<Event>)
+                    -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    thread::spawn(move || {
+        let result = listener.accept().and_then(|(stream, _)| serve(stream, &counter, &events));
+        if let Err(e) = result {
+            eprintln!("Control connection failed: {}", e);
+        }
+        let _ = events.send(Event::Closed);
+    });
+    Ok(())
+}
+
+fn serve(mut stream: TcpStream, counter: &RxCounter, events: &Sender<Event>) -> io::Result<()> {
+    stream.set_nodelay(true)?;
+    let reader = BufReader::new(stream.try_clone()?);
+    let mut start = (counter.get(), Instant::now());
+    for line in reader.lines() {
+        let line = line?;
+        let mut words = line.splitn(2, ' ');
+        match words.next() {
+            Some("CASE") => {
+                let _ = events.send(Event::Case(words.next().unwrap_or("").to_owned()));
+                writeln!(stream, "OK")?;
+            }
+            Some("START") => {
+                start = (counter.get(), Instant::now());
+                writeln!(stream, "OK")?;
+            }
+            Some("STOP") => {
+                let ((start_packets, start_bytes), start_time) = start;
+                let (packets, bytes) = counter.get();
+                let packets = packets - start_packets;
+                let bytes = bytes - start_bytes;
+                writeln!(stream, "RESULT {} {}", packets, bytes)?;
+                let _ = events.send(Event::Result {
+                    packets: packets,
+                    bytes: bytes,
+                    duration: start_time.elapsed(),
+                });
+            }
+            _ => return Err(invalid_response(&line)),
+        }
+    }
+    Ok(())
+}
+
+fn expect_ok(response: String) -> io::Result<()> {
+    if response == "OK" {
+        Ok(())
+    } else {
+        Err(invalid_response(&response))
+    }
+}
+
+fn invalid_response(response: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData,
+                   format!("Invalid control message: {}", response))
+}
@@ -0,0 +1,84 @@
+use progress::duration_secs;
+use results::JsonObject;
+
+use std::time::Duration;
+
+/// CPU time and context switches of the whole process, as reported by
+/// `getrusage`.
+#[derive(Debug, Clone, Copy)]
+pub struct CpuUsage {
+    pub user: Duration,
+    pub system: Duration,
+    pub voluntary_switches: u64,
+    pub involuntary_switches: u64,
+}
+
+impl CpuUsage {
+    /// Usage so far, or `None` where `getrusage` is not available.
+    #[cfg(target_os = "linux")]
+    pub fn now() -> Option<Self> {
+        use libc;
+        use std::mem;
+
+        let mut usage: libc::rusage = unsafe { mem::zeroed() };
+        if unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) } != 0 {
+            return None;
+        }
+        Some(CpuUsage {
+            user: timeval_to_duration(usage.ru_utime),
+            system: timeval_to_duration(usage.ru_stime),
+            voluntary_switches: usage.ru_nvcsw as u64,
+            involuntary_switches: usage.ru_nivcsw as u64,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn now() -> Option<Self> {
+        None
+    }
+
+    /// Usage between `start` and `self`.
+    pub fn since(&self, start: &CpuUsage) -> CpuUsage {
+        CpuUsage {
+            user: self.user - start.user,
+            system: self.system - start.system,
+            voluntary_switches: self.voluntary_switches - start.voluntary_switches,
+            involuntary_switches: self.involuntary_switches - start.involuntary_switches,
+        }
+    }
+
+    pub fn add(&mut self, other: &CpuUsage) {
+        self.user += other.user;
+        self.system += other.system;
+        self.voluntary_switches += other.voluntary_switches;
+        self.involuntary_switches += other.involuntary_switches;
+    }
+
+    /// Microseconds of user and system time spent per packet.
+    pub fn micros_per_packet(&self, packets: usize) -> (f64, f64) {
+        let packets = packets as f64;
+        (duration_secs(self.user) * 1_000_000f64 / packets,
+         duration_secs(self.system) * 1_000_000f64 / packets)
+    }
+
+    pub fn to_json(&self, packets: usize) -> JsonObject {
+        let json = JsonObject::new()
+            .number("user", duration_secs(self.user))
+            .number("system", duration_secs(self.system))
+            .number("voluntary_switches", self.voluntary_switches)
+            .number("involuntary_switches", self.involuntary_switches);
+        if packets > 0 {
+            let (user_per_packet, system_per_packet) = self.micros_per_packet(packets);
+            json.number("user_us_per_packet", user_per_packet)
+                .number("system_us_per_packet", system_per_packet)
+                .number("cpu_us_per_packet", user_per_packet + system_per_packet)
+        } else {
+            json
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn timeval_to_duration(time: ::libc::timeval) -> Duration {
+    Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000)
+}
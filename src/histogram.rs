@@ -0,0 +1,228 @@
+use latency::micros;
+use progress::duration_secs;
+use results::JsonObject;
+
+use std::time::Duration;
+
+/// Sub-buckets per power of two. Values are recorded with a relative error
+/// of at most 1/32.
+const SUB_BUCKET_BITS: u32 = 5;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+
+/// HDR style histogram of durations with nanosecond resolution. Buckets are
+/// linear below `SUB_BUCKETS` ns and log-linear above, so memory stays small
+/// while the tail keeps its precision.
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    count: u64,
+    /// Exact extremes in nanoseconds, the buckets only bound them.
+    min: u64,
+    max: u64,
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Histogram {
+            counts: Vec::new(),
+            count: 0,
+            min: u64::max_value(),
+            max: 0,
+        }
+    }
+
+    pub fn record(&mut self, duration: Duration) {
+        let nanos = duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64;
+        let index = bucket_index(nanos);
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+        self.count += 1;
+        self.min = self.min.min(nanos);
+        self.max = self.max.max(nanos);
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        if other.counts.len() > self.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (count, other_count) in self.counts.iter_mut().zip(&other.counts) {
+            *count += *other_count;
+        }
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Number of recorded values.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The highest value equivalent to the `p`th percentile.
+    pub fn percentile(&self, p: f64) -> Duration {
+        let rank = ((p / 100.0 * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += *count;
+            if seen >= rank {
+                return nanos_to_duration(bucket_high(index).min(self.max));
+            }
+        }
+        nanos_to_duration(self.max)
+    }
+
+    /// The lowest recorded value, or zero if nothing was recorded.
+    pub fn min(&self) -> Duration {
+        if self.is_empty() {
+            Duration::new(0, 0)
+        } else {
+            nanos_to_duration(self.min)
+        }
+    }
+
+    pub fn max(&self) -> Duration {
+        nanos_to_duration(self.max)
+    }
+
+    /// Short human readable summary of the tail, in microseconds.
+    pub fn status(&self) -> String {
+        format!("p50/p90/p99/p99.9/max = {:.1}/{:.1}/{:.1}/{:.1}/{:.1} µs",
+                micros(self.percentile(50.0)),
+                micros(self.percentile(90.0)),
+                micros(self.percentile(99.0)),
+                micros(self.percentile(99.9)),
+                micros(self.max()))
+    }
+
+    /// Percentiles in seconds, and the count of every non-empty bucket keyed
+    /// by the highest nanosecond value it holds.
+    pub fn to_json(&self) -> JsonObject {
+        let mut buckets = JsonObject::new();
+        for (index, count) in self.counts.iter().enumerate() {
+            if *count > 0 {
+                buckets = buckets.number(&bucket_high(index).to_string(), count);
+            }
+        }
+        JsonObject::new()
+            .number("count", self.count)
+            .number("min", duration_secs(self.min()))
+            .number("p50", duration_secs(self.percentile(50.0)))
+            .number("p90", duration_secs(self.percentile(90.0)))
+            .number("p99", duration_secs(self.percentile(99.0)))
+            .number("p999", duration_secs(self.percentile(99.9)))
+            .number("max", duration_secs(self.max()))
+            .object("buckets", buckets)
+    }
+}
+
+fn bucket_index(nanos: u64) -> usize {
+    let bits = 64 - nanos.leading_zeros();
+    if bits <= SUB_BUCKET_BITS {
+        return nanos as usize;
+    }
+    let shift = bits - SUB_BUCKET_BITS - 1;
+    let sub_bucket = (nanos >> shift) as usize - SUB_BUCKETS;
+    (shift as usize + 1) * SUB_BUCKETS + sub_bucket
+}
+
+/// Highest value that ends up in bucket `index`.
+fn bucket_high(index: usize) -> u64 {
+    if index < 2 * SUB_BUCKETS {
+        return index as u64;
+    }
+    let shift = index / SUB_BUCKETS - 1;
+    let low = ((index % SUB_BUCKETS + SUB_BUCKETS) as u64) << shift;
+    low + (1 << shift) - 1
+}
+
+fn nanos_to_duration(nanos: u64) -> Duration {
+    Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Histogram, bucket_high, bucket_index, nanos_to_duration};
+
+    fn histogram(nanos: &[u64]) -> Histogram {
+        let mut histogram = Histogram::new();
+        for &value in nanos {
+            histogram.record(nanos_to_duration(value));
+        }
+        histogram
+    }
+
+    #[test]
+    fn buckets_round_trip_boundaries() {
+        assert_eq!(bucket_index(31), 31);
+        assert_eq!(bucket_index(32), 32);
+        assert_eq!(bucket_index(63), 63);
+        assert_eq!(bucket_index(64), 64);
+        assert_eq!(bucket_index(65), 64);
+        assert_eq!(bucket_index(127), 95);
+        assert_eq!(bucket_index(128), 96);
+        assert_eq!(bucket_high(31), 31);
+        assert_eq!(bucket_high(32), 32);
+        assert_eq!(bucket_high(63), 63);
+        assert_eq!(bucket_high(64), 65);
+        assert_eq!(bucket_high(95), 127);
+        for index in 0..1000 {
+            assert_eq!(bucket_index(bucket_high(index)), index);
+        }
+    }
+
+    #[test]
+    fn buckets_bound_values() {
+        for nanos in (0..100_000).chain(vec![u32::max_value() as u64, 1 << 40]) {
+            let high = bucket_high(bucket_index(nanos));
+            assert!(high >= nanos);
+            assert!(high - nanos <= nanos / 32, "{} in bucket up to {}", nanos, high);
+        }
+    }
+
+    #[test]
+    fn percentiles_of_few_values() {
+        let histogram = histogram(&[40, 10, 30, 20]);
+        assert_eq!(histogram.percentile(0.0), nanos_to_duration(10));
+        assert_eq!(histogram.percentile(25.0), nanos_to_duration(10));
+        assert_eq!(histogram.percentile(50.0), nanos_to_duration(20));
+        assert_eq!(histogram.percentile(90.0), nanos_to_duration(40));
+        assert_eq!(histogram.percentile(100.0), nanos_to_duration(40));
+
+        // Capped by the exact maximum, not the top of its bucket.
+        let histogram = histogram(&[1000]);
+        assert_eq!(histogram.percentile(50.0), nanos_to_duration(1000));
+        assert_eq!(histogram.min(), nanos_to_duration(1000));
+    }
+
+    #[test]
+    fn merges() {
+        let mut merged = histogram(&[10, 20]);
+        merged.merge(&histogram(&[30, 40, 5000]));
+        merged.merge(&Histogram::new());
+        assert_eq!(merged.count(), 5);
+        assert_eq!(merged.min(), nanos_to_duration(10));
+        assert_eq!(merged.max(), nanos_to_duration(5000));
+        assert_eq!(merged.percentile(50.0), nanos_to_duration(30));
+
+        let mut empty = Histogram::new();
+        empty.merge(&merged);
+        assert_eq!(empty.count(), 5);
+        assert_eq!(empty.min(), nanos_to_duration(10));
+    }
+
+    #[test]
+    fn empty() {
+        let histogram = Histogram::new();
+        assert!(histogram.is_empty());
+        assert_eq!(histogram.count(), 0);
+        assert_eq!(histogram.min(), nanos_to_duration(0));
+        assert_eq!(histogram.max(), nanos_to_duration(0));
+        assert_eq!(histogram.percentile(99.0), nanos_to_duration(0));
+    }
+}
@@ -0,0 +1,273 @@
+//! Baseline suite sending through the kernel network stack, to put the rips
+//! numbers in relation to what the operating system itself achieves.
+
+use Config;
+use bench::{self, Benchmark, SendFn};
+use progress::Printer;
+use rips::EthernetChannel;
+use rips_bench::{self, Protocol};
+use rx::{self, RxCounter, RxThread};
+use sequence::Stream;
+use wire::Encapsulation;
+
+use std::io;
+use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, SystemTime};
+
+/// Datagrams per call. Batches larger than one are sent with `sendmmsg`.
+#[cfg(target_os = "linux")]
+const BATCHES: &'static [usize] = &[1, 10, 100, 1000];
+#[cfg(not(target_os = "linux"))]
+const BATCHES: &'static [usize] = &[1];
+
+/// Sends UDP datagrams with the same payload sizes as the rips UDP suite to
+/// `config.dst`. The kernel picks the source address and port, so the
+/// interface given to rips is not used.
+pub struct KernelBench {
+    socket: UdpSocket,
+    stream: Arc<Stream>,
+}
+
+impl Benchmark for KernelBench {
+    fn setup(_channel: EthernetChannel,
+             _config: &Config,
+             _printer: &mut Printer)
+             -> io::Result<Self> {
+        Ok(KernelBench {
+            socket: bind(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), 0))?,
+            stream: Arc::new(Stream::new()),
+        })
+    }
+
+    fn title(&self) -> &'static str {
+        "Kernel UDP sending"
+    }
+
+    fn encapsulation(&self) -> Encapsulation {
+        Encapsulation::Udp
+    }
+
+    /// Batches are sent with `sendmmsg`, which only Linux has.
+    fn batches(&self, config: &Config) -> Vec<usize> {
+        if cfg!(target_os = "linux") {
+            bench::batches(config, BATCHES)
+        } else {
+            BATCHES.to_vec()
+        }
+    }
+
+    fn sizes(&self, config: &Config, printer: &mut Printer) -> Vec<usize> {
+        rips_bench::packet_sizes(config, Protocol::Udp, printer)
+    }
+
+    /// Every sender gets a clone of the socket.
+    fn sender(&mut self, config: &Config, _thread: usize) -> io::Result<Option<SendFn>> {
+        let socket = self.socket.try_clone()?;
+        let stream = self.stream.clone();
+        let dst = config.dst;
+        let stamp = config.stamp;
+        let mut buffer = Vec::new();
+        let mut batch: Option<mmsg::MessageBatch> = None;
+        Ok(Some(Box::new(move |packets_per_call: usize, bytes_per_packet: usize| {
+            if packets_per_call == 1 {
+                buffer.resize(bytes_per_packet, 0);
+                if stamp {
+                    let seq = stream.reserve(1);
+                    stream.stamp(&mut buffer, seq, SystemTime::now());
+                }
+                return socket.send_to(&buffer, dst).map(|_size| Some((1, bytes_per_packet)));
+            }
+
+            let reusable = match batch {
+                Some(ref batch) => {
+                    batch.packets() == packets_per_call &&
+                    batch.bytes_per_packet() == bytes_per_packet
+                }
+                None => false,
+            };
+            if !reusable {
+                batch = Some(mmsg::MessageBatch::new(packets_per_call, bytes_per_packet, dst));
+            }
+            let batch = batch.as_mut().unwrap();
+            if stamp {
+                let now = SystemTime::now();
+                let first_seq = stream.reserve(packets_per_call);
+                for i in 0..packets_per_call {
+                    stream.stamp(batch.payload_mut(i), first_seq + i as u64, now);
+                }
+            }
+            batch.send(&socket).map(|sent| Some((sent, sent * bytes_per_packet)))
+        })))
+    }
+}
+
+/// Receives datagrams on the port of `config.src` through the kernel.
+pub fn bench_rx(_channel: EthernetChannel,
+                config: &Config,
+                printer: &mut Printer)
+                -> io::Result<()> {
+    let socket = bind(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), config.src.port()))?;
+
+    printer.print_title("Kernel UDP receiving");
+    printer.set_encapsulation(Encapsulation::Udp);
+    printer.start_case(&format!("Receiving datagrams on port {}", config.src.port()),
+                       1,
+                       0)?;
+    let counter = RxCounter::new();
+    let thread_counter = counter.clone();
+    socket.set_read_timeout(Some(Duration::from_millis(rx::READ_TIMEOUT_MS)))?;
+    let rx_thread = RxThread::spawn(move |running| {
+        let mut buffer = vec![0; 65535];
+        while running.load(Ordering::Relaxed) {
+            match socket.recv_from(&mut buffer) {
+                Ok((size, _src)) => thread_counter.add(size),
+                Err(ref e) if rx::is_timeout(e) => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    });
+    let measured = rx::measure(printer, &counter, config);
+    // Closes the socket, so the port can be bound again.
+    rx_thread.stop()?;
+    measured?;
+    printer.end_case();
+    Ok(())
+}
+
+fn bind(addr: SocketAddrV4) -> io::Result<UdpSocket> {
+    UdpSocket::bind(addr).map_err(|e| {
+        bench::context(e, &format!("Unable to bind kernel UDP socket to {}", addr))
+    })
+}
+
+#[cfg(target_os = "linux")]
+mod mmsg {
+    use libc;
+
+    use std::io;
+    use std::mem;
+    use std::net::{SocketAddrV4, UdpSocket};
+    use std::os::unix::io::AsRawFd;
+
+    /// A batch of datagrams to the same destination, sent with a single
+    /// `sendmmsg` call. The headers point into the buffers owned by the
+    /// batch, which are never reallocated.
+    pub struct MessageBatch {
+        headers: Vec<libc::mmsghdr>,
+        _iovecs: Vec<libc::iovec>,
+        _addr: Box<libc::sockaddr_in>,
+        buffer: Vec<u8>,
+        bytes_per_packet: usize,
+    }
+
+    // The raw pointers only ever point into memory owned by the batch itself.
+    unsafe impl Send for MessageBatch {}
+
+    impl MessageBatch {
+        pub fn new(packets: usize, bytes_per_packet: usize, dst: SocketAddrV4) -> Self {
+            let mut buffer = vec![0; packets * bytes_per_packet];
+            let mut addr: Box<libc::sockaddr_in> = Box::new(unsafe { mem::zeroed() });
+            addr.sin_family = libc::AF_INET as libc::sa_family_t;
+            addr.sin_port = dst.port().to_be();
+            addr.sin_addr = libc::in_addr { s_addr: u32::from(*dst.ip()).to_be() };
+
+            let mut iovecs: Vec<libc::iovec> = (0..packets)
+                .map(|i| {
+                    libc::iovec {
+                        iov_base: unsafe {
+                            buffer.as_mut_ptr().offset((i * bytes_per_packet) as isize)
+                        } as *mut libc::c_void,
+                        iov_len: bytes_per_packet,
+                    }
+                })
+                .collect();
+            let addr_ptr = &mut *addr as *mut libc::sockaddr_in as *mut libc::c_void;
+            let headers = iovecs.iter_mut()
+                .map(|iovec| {
+                    let mut header: libc::mmsghdr = unsafe { mem::zeroed() };
+                    header.msg_hdr.msg_name = addr_ptr;
+                    header.msg_hdr.msg_namelen = mem::size_of::<libc::sockaddr_in>() as
+                                                 libc::socklen_t;
+                    header.msg_hdr.msg_iov = iovec as *mut libc::iovec;
+                    header.msg_hdr.msg_iovlen = 1;
+                    header
+                })
+                .collect();
+            MessageBatch {
+                headers: headers,
+                _iovecs: iovecs,
+                _addr: addr,
+                buffer: buffer,
+                bytes_per_packet: bytes_per_packet,
+            }
+        }
+
+        pub fn packets(&self) -> usize {
+            self.headers.len()
+        }
+
+        pub fn bytes_per_packet(&self) -> usize {
+            self.bytes_per_packet
+        }
+
+        pub fn payload_mut(&mut self, i: usize) -> &mut [u8] {
+            let start = i * self.bytes_per_packet;
+            &mut self.buffer[start..start + self.bytes_per_packet]
+        }
+
+        /// Sends the batch and returns how many of the datagrams were sent.
+        pub fn send(&mut self, socket: &UdpSocket) -> io::Result<usize> {
+            let sent = unsafe {
+                libc::sendmmsg(socket.as_raw_fd(),
+                               self.headers.as_mut_ptr(),
+                               self.headers.len() as libc::c_uint,
+                               0)
+            };
+            if sent < 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                Ok(sent as usize)
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod mmsg {
+    use std::io;
+    use std::net::{SocketAddrV4, UdpSocket};
+
+    /// Stand-in for platforms without `sendmmsg`, never sending anything.
+    pub struct MessageBatch {
+        packets: usize,
+        buffer: Vec<u8>,
+    }
+
+    impl MessageBatch {
+        pub fn new(packets: usize, bytes_per_packet: usize, _dst: SocketAddrV4) -> Self {
+            MessageBatch {
+                packets: packets,
+                buffer: vec![0; bytes_per_packet],
+            }
+        }
+
+        pub fn packets(&self) -> usize {
+            self.packets
+        }
+
+        pub fn bytes_per_packet(&self) -> usize {
+            self.buffer.len()
+        }
+
+        pub fn payload_mut(&mut self, _i: usize) -> &mut [u8] {
+            &mut self.buffer
+        }
+
+        pub fn send(&mut self, _socket: &UdpSocket) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::Other, "sendmmsg is only available on Linux"))
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use histogram::Histogram;
+use progress::duration_secs;
+use results::JsonObject;
+
+use std::time::Duration;
+
+/// Round-trip times of the requests answered during a benchmark case and
+/// the number of requests that were never answered. The times are kept in a
+/// `Histogram`, like the send call durations.
+#[derive(Debug, Clone)]
+pub struct Latency {
+    rtts: Histogram,
+    lost: usize,
+}
+
+impl Latency {
+    pub fn new() -> Self {
+        Latency {
+            rtts: Histogram::new(),
+            lost: 0,
+        }
+    }
+
+    pub fn add(&mut self, rtt: Duration) {
+        self.rtts.record(rtt);
+    }
+
+    pub fn add_lost(&mut self) {
+        self.lost += 1;
+    }
+
+    pub fn merge(&mut self, other: &Latency) {
+        self.rtts.merge(&other.rtts);
+        self.lost += other.lost;
+    }
+
+    /// Fraction of the requests that were never answered.
+    pub fn loss(&self) -> f64 {
+        let sent = self.rtts.count() as usize + self.lost;
+        if sent > 0 {
+            self.lost as f64 / sent as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// Short human readable summary of the round-trip times and the loss.
+    pub fn status(&self) -> String {
+        format!("min/median/p99/max = {:.1}/{:.1}/{:.1}/{:.1} µs, {:.2}% loss",
+                micros(self.rtts.min()),
+                micros(self.rtts.percentile(50.0)),
+                micros(self.rtts.percentile(99.0)),
+                micros(self.rtts.max()),
+                self.loss() * 100.0)
+    }
+
+    /// The histogram of the round-trip times, with the lost requests added.
+    /// `median` is kept next to `p50` so older results still compare.
+    pub fn to_json(&self) -> JsonObject {
+        self.rtts
+            .to_json()
+            .number("median", duration_secs(self.rtts.percentile(50.0)))
+            .number("lost", self.lost)
+            .number("loss", self.loss())
+    }
+}
+
+pub fn micros(duration: Duration) -> f64 {
+    duration_secs(duration) * 1_000_000f64
+}
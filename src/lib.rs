@@ -0,0 +1,215 @@
+//! Benchmarking of rips, the Rust userspace IP network stack. The `ripsbench`
+//! binary is a command line interface to this library, which can also be
+//! used to run the suites from other crates, e.g. against a mock channel.
+
+#[macro_use]
+extern crate clap;
+#[macro_use]
+extern crate lazy_static;
+#[cfg(target_os = "linux")]
+extern crate libc;
+extern crate pnet;
+extern crate rips;
+
+#[macro_use]
+pub mod args;
+pub mod bench;
+pub mod control;
+pub mod cpu;
+pub mod histogram;
+pub mod latency;
+pub mod mock;
+pub mod nic;
+pub mod placement;
+pub mod progress;
+pub mod results;
+pub mod rx;
+
+mod checksum_bench;
+mod construct_bench;
+mod kernel_bench;
+mod pnet_bench;
+pub mod reflect;
+mod rips_bench;
+mod rtt_bench;
+mod sequence;
+pub mod sink;
+pub mod size_mix;
+pub mod suites;
+mod threads;
+pub mod wire;
+
+pub use results::Record;
+pub use suites::Protocol;
+
+use rips::{EthernetChannel, Interface};
+use rips::ethernet::MacAddr;
+use rips::ipv4::Ipv4Network;
+use std::io;
+use std::net::{SocketAddr, SocketAddrV4, Ipv4Addr};
+use std::path::PathBuf;
+use std::time::Duration;
+
+lazy_static! {
+    static ref DEFAULT_IFACE: Interface = Interface { name: "test0".to_owned(), mac: *SRC_MAC };
+    static ref SRC_MAC: MacAddr = MacAddr::new(11, 22, 33, 44, 55, 66);
+    static ref SRC_IP: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
+    static ref SRC_NET: Ipv4Network = Ipv4Network::new(*SRC_IP, 24).unwrap();
+    static ref SRC_SOCKETADDR: SocketAddrV4 = SocketAddrV4::new(*SRC_IP, 0);
+
+    static ref GW: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+
+    static ref DST_MAC: MacAddr = MacAddr::new(99, 88, 77, 66, 55, 44);
+    static ref DST_LAN_IP: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 15);
+    static ref DST_LAN_SOCKETADDR: SocketAddrV4 = SocketAddrV4::new(*DST_LAN_IP, 8080);
+}
+
+#[derive(Debug)]
+pub struct Config {
+    pub duration: Duration,
+    pub warmup: Duration,
+    pub repeat: usize,
+    pub threads: usize,
+    pub placement: placement::Placement,
+    pub iface: rips::Interface,
+    pub mtu: usize,
+    pub src_mac: MacAddr,
+    pub src: SocketAddrV4,
+    pub src_net: Ipv4Network,
+    pub dst_mac: MacAddr,
+    pub resolve_dst_mac: bool,
+    pub dst: SocketAddrV4,
+    pub gw: Ipv4Addr,
+    pub size_min: bool,
+    /// Send packets filling the MTU. The only size set by `Config::new`, so
+    /// every suite has a case to run.
+    pub size_mtu: bool,
+    pub size_max: bool,
+    /// IP packet sizes in bytes, translated into the payload of every suite.
+    pub sizes: Vec<usize>,
+    /// Draw the size of every packet from this mix instead.
+    pub size_mix: Option<size_mix::SizeMix>,
+    /// Packets per send call, or empty for the defaults of every suite.
+    pub batches: Vec<usize>,
+    pub tx: bool,
+    pub rx: bool,
+    pub output: args::Output,
+    pub results_path: Option<PathBuf>,
+    pub timeseries_path: Option<PathBuf>,
+    pub stamp: bool,
+    /// Time every send call, see `Record::send_latency`.
+    pub send_latency: bool,
+    pub control: Option<SocketAddr>,
+    pub backend: args::Backend,
+    pub mock_capture: usize,
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Config {
+            duration: Duration::new(10, 0),
+            warmup: Duration::new(0, 0),
+            repeat: 1,
+            threads: 1,
+            placement: placement::Placement::new(),
+            iface: (*DEFAULT_IFACE).clone(),
+            mtu: 1500,
+            src_mac: *SRC_MAC,
+            src: *SRC_SOCKETADDR,
+            src_net: *SRC_NET,
+            dst_mac: *DST_MAC,
+            resolve_dst_mac: false,
+            dst: *DST_LAN_SOCKETADDR,
+            gw: *GW,
+            size_min: false,
+            size_mtu: true,
+            size_max: false,
+            sizes: Vec::new(),
+            size_mix: None,
+            batches: Vec::new(),
+            tx: true,
+            rx: false,
+            output: args::Output::Human,
+            results_path: None,
+            timeseries_path: None,
+            stamp: false,
+            send_latency: false,
+            control: None,
+            backend: args::Backend::Pnet,
+            mock_capture: 0,
+        }
+    }
+
+    pub fn to_json(&self) -> results::JsonObject {
+        let json = results::JsonObject::new()
+            .number("duration", progress::duration_secs(self.duration))
+            .number("warmup", progress::duration_secs(self.warmup))
+            .number("repeat", self.repeat)
+            .number("threads", self.threads)
+            .object("placement", self.placement.to_json())
+            .string("iface", &self.iface.name)
+            .number("mtu", self.mtu)
+            .string("src_mac", self.src_mac)
+            .string("src", self.src)
+            .string("src_net", self.src_net)
+            .string("dst_mac", self.dst_mac)
+            .bool("resolve_dst_mac", self.resolve_dst_mac)
+            .string("dst", self.dst)
+            .string("gw", self.gw)
+            .bool("size_min", self.size_min)
+            .bool("size_mtu", self.size_mtu)
+            .bool("size_max", self.size_max)
+            .string("sizes",
+                    self.sizes.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(","))
+            .string("batches",
+                    self.batches.iter().map(|b| b.to_string()).collect::<Vec<_>>().join(","))
+            .bool("tx", self.tx)
+            .bool("rx", self.rx)
+            .string("backend", &self.backend)
+            .bool("stamp", self.stamp)
+            .bool("send_latency", self.send_latency);
+        let json = match self.size_mix {
+            Some(ref size_mix) => json.string("size_mix", size_mix),
+            None => json,
+        };
+        match self.control {
+            Some(control) => json.string("control", control),
+            None => json,
+        }
+    }
+}
+
+/// Runs the sending side of the suite selected by `protocol` over `channel`
+/// and returns the result of every case. Progress is printed as configured
+/// by `config.output`. Stops at the first error, like a failed send call or
+/// a results file that can't be created.
+pub fn run_tx(protocol: Protocol,
+              channel: EthernetChannel,
+              config: &Config)
+              -> io::Result<Vec<Record>> {
+    let mut printer = progress::Printer::new(config)?;
+    (protocol.suite().tx)(channel, config, &mut printer)?;
+    Ok(printer.into_results())
+}
+
+/// Like `run_tx`, but for the receiving side. The channel is created by
+/// `create_channel`, given the read timeout it must have for the suite to be
+/// able to stop its receive thread. Returns `None` if the suite has no
+/// receive benchmark.
+pub fn run_rx<F>(protocol: Protocol,
+                 create_channel: F,
+                 config: &Config)
+                 -> io::Result<Option<Vec<Record>>>
+    where F: FnOnce(Option<Duration>) -> io::Result<EthernetChannel>
+{
+    let suite = protocol.suite();
+    match suite.rx {
+        Some(rx) => {
+            let channel = create_channel(suite.rx_read_timeout())?;
+            let mut printer = progress::Printer::new(config)?;
+            rx(channel, config, &mut printer)?;
+            Ok(Some(printer.into_results()))
+        }
+        None => Ok(None),
+    }
+}
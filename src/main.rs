@@ -1,103 +1,175 @@
-#[macro_use]
-extern crate clap;
-#[macro_use]
-extern crate lazy_static;
-extern crate pnet;
+extern crate ripsbench;
 extern crate rips;
 
-#[macro_use]
-mod args;
-mod progress;
+use ripsbench::{Config, args, control, mock, progress, reflect, sink};
 
-mod pnet_bench;
-mod rips_bench;
-
-use rips::Interface;
-use rips::ethernet::MacAddr;
-use rips::ipv4::Ipv4Network;
-use std::net::{SocketAddrV4, Ipv4Addr};
+use rips::EthernetChannel;
+use std::io::{self, Write};
+use std::net::SocketAddrV4;
+use std::process;
 use std::time::Duration;
 
-lazy_static! {
-    static ref DEFAULT_IFACE: Interface = Interface { name: "test0".to_owned(), mac: *SRC_MAC };
-    static ref SRC_MAC: MacAddr = MacAddr::new(11, 22, 33, 44, 55, 66);
-    static ref SRC_IP: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 2);
-    static ref SRC_NET: Ipv4Network = Ipv4Network::new(*SRC_IP, 24).unwrap();
-    static ref SRC_SOCKETADDR: SocketAddrV4 = SocketAddrV4::new(*SRC_IP, 0);
-
-    static ref GW: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
-
-    static ref DST_MAC: MacAddr = MacAddr::new(99, 88, 77, 66, 55, 44);
-    static ref DST_LAN_IP: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 15);
-    static ref DST_LAN_SOCKETADDR: SocketAddrV4 = SocketAddrV4::new(*DST_LAN_IP, 8080);
-}
-
-#[derive(Debug)]
-pub struct Config {
-    pub duration: Duration,
-    pub iface: rips::Interface,
-    pub mtu: usize,
-    pub src_mac: MacAddr,
-    pub src: SocketAddrV4,
-    pub src_net: Ipv4Network,
-    pub dst_mac: MacAddr,
-    pub dst: SocketAddrV4,
-    pub gw: Ipv4Addr,
-    pub size_min: bool,
-    pub size_mtu: bool,
-    pub size_max: bool,
-}
-
-impl Config {
-    pub fn new() -> Self {
-        Config {
-            duration: Duration::new(10, 0),
-            iface: (*DEFAULT_IFACE).clone(),
-            mtu: 1500,
-            src_mac: *SRC_MAC,
-            src: *SRC_SOCKETADDR,
-            src_net: *SRC_NET,
-            dst_mac: *DST_MAC,
-            dst: *DST_LAN_SOCKETADDR,
-            gw: *GW,
-            size_min: false,
-            size_mtu: false,
-            size_max: false,
+fn main() {
+    let args = args::ArgumentParser::new();
+    let config = create_config(&args);
+    match args.get_mode() {
+        args::Mode::Bench => run_benchmarks(&args, config),
+        args::Mode::Reflect => {
+            let mut printer = exit_on_error(progress::Printer::new(&config));
+            let (channel, _) = create_channel(&args, &config, None);
+            exit_on_error(reflect::run(channel, &config, &mut printer));
         }
+        args::Mode::Sink => run_sink(&args, config),
     }
 }
 
-fn main() {
-    let args = args::ArgumentParser::new();
-
-    let (_, iface) = args.get_iface();
+/// Creates a `Config` from the arguments common to all modes.
+fn create_config(args: &args::ArgumentParser) -> Config {
+    let iface = args.get_rips_iface();
     let src_net = args.get_src_net();
     let src_port = args.get_src_port();
     let src = SocketAddrV4::new(src_net.ip(), src_port);
 
     let mut config = Config::new();
     config.iface = iface;
-    config.mtu = args.get_mtu();
     config.src = src;
     config.src_net = src_net;
-    config.dst = args.get_dst();
     config.gw = args.get_gw();
-    for size in args.get_sizes() {
+    config.backend = args.get_backend();
+    config.mock_capture = args.get_mock_capture();
+    config
+}
+
+fn run_benchmarks(args: &args::ArgumentParser, mut config: Config) {
+    config.duration = args.get_duration();
+    config.warmup = args.get_warmup();
+    config.repeat = args.get_repeat();
+    config.threads = args.get_threads();
+    config.placement = args.get_placement();
+    config.mtu = args.get_mtu();
+    config.dst = args.get_dst();
+    match args.get_dst_mac() {
+        Some(dst_mac) => config.dst_mac = dst_mac,
+        None => config.resolve_dst_mac = true,
+    }
+    let sizes = args.get_sizes();
+    // Sizes given on the command line replace the default one.
+    if !sizes.is_empty() {
+        config.size_mtu = false;
+    }
+    for size in sizes {
         match size {
             args::Size::Min => config.size_min = true,
             args::Size::Mtu => config.size_mtu = true,
             args::Size::Max => config.size_max = true,
+            args::Size::Bytes(bytes) => config.sizes.push(bytes),
         }
     }
+    config.size_mix = args.get_size_mix();
+    config.batches = args.get_batches();
 
-    println!("CONFIG: {:?}", &config);
+    match args.get_direction() {
+        args::Direction::Tx => (),
+        args::Direction::Rx => {
+            config.tx = false;
+            config.rx = true;
+        }
+        args::Direction::Both => config.rx = true,
+    }
+
+    config.output = args.get_output();
+    config.results_path = args.get_results_path();
+    config.timeseries_path = args.get_timeseries_path();
+    config.stamp = args.get_stamp();
+    config.send_latency = args.get_send_latency();
+    config.control = args.get_control();
+
+    let mut printer = exit_on_error(progress::Printer::new(&config));
+    printer.print_message(&format!("CONFIG: {:?}", &config));
+    if let Some(control_addr) = config.control {
+        match control::ControlClient::connect(control_addr) {
+            Ok(control) => printer.set_control(control),
+            Err(e) => {
+                let _ = writeln!(io::stderr(),
+                                 "Unable to connect to the sink at {}: {}",
+                                 control_addr,
+                                 e);
+                process::exit(1);
+            }
+        }
+    }
+
+    // The main thread runs all single threaded suites. With several threads
+    // the senders get the placement slots instead, and the main thread
+    // sampling them is left unpinned and non-realtime. A sender that never
+    // blocks must not keep it from stopping the case.
+    if config.threads == 1 {
+        exit_on_error(config.placement.apply(0));
+    }
 
     for protocol in args.get_protocols() {
-        match protocol {
-            args::Protocol::Pnet => pnet_bench::bench(args.create_channel(), &config),
-            args::Protocol::Ethernet => rips_bench::bench_ethernet(args.create_channel(), &config),
-            args::Protocol::Ipv4 => rips_bench::bench_ipv4(args.create_channel(), &config),
-            args::Protocol::Udp => rips_bench::bench_udp(args.create_channel(), &config),
+        let suite = protocol.suite();
+        if config.tx {
+            let (channel, mock_handle) = if suite.discard {
+                create_mock_channel(args, &config, None)
+            } else {
+                create_channel(args, &config, None)
+            };
+            exit_on_error((suite.tx)(channel, &config, &mut printer));
+            if let Some(mock_handle) = mock_handle {
+                mock_handle.print_summary(&mut printer);
+            }
+        }
+        if let (true, Some(rx)) = (config.rx, suite.rx) {
+            let (channel, mock_handle) = create_channel(args, &config, suite.rx_read_timeout());
+            exit_on_error(rx(channel, &config, &mut printer));
+            if let Some(mock_handle) = mock_handle {
+                mock_handle.print_summary(&mut printer);
+            }
+        }
+    }
+}
+
+fn run_sink(args: &args::ArgumentParser, mut config: Config) {
+    config.output = args.get_output();
+    config.results_path = args.get_results_path();
+    config.timeseries_path = args.get_timeseries_path();
+    config.control = args.get_control();
+
+    let mut printer = exit_on_error(progress::Printer::new(&config));
+    let (channel, _) = create_channel(args, &config, None);
+    exit_on_error(sink::run(channel,
+                            &config,
+                            args.get_sink_receiver(),
+                            args.get_sink_duration(),
+                            &mut printer));
+}
+
+fn create_channel(args: &args::ArgumentParser,
+                  config: &Config,
+                  read_timeout: Option<Duration>)
+                  -> (EthernetChannel, Option<mock::MockHandle>) {
+    match config.backend {
+        args::Backend::Pnet => (args.create_channel(read_timeout), None),
+        args::Backend::Mock => create_mock_channel(args, config, read_timeout),
+    }
+}
+
+fn create_mock_channel(args: &args::ArgumentParser,
+                       config: &Config,
+                       read_timeout: Option<Duration>)
+                       -> (EthernetChannel, Option<mock::MockHandle>) {
+    let (channel, handle) = mock::channel(args.get_netbuf(), read_timeout, config);
+    (channel, Some(handle))
+}
+
+/// Prints the error a benchmark stopped at and exits.
+fn exit_on_error<T>(result: io::Result<T>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(e) => {
+            let _ = writeln!(io::stderr(), "{}", e);
+            process::exit(1);
         }
     }
 }
@@ -0,0 +1,217 @@
+use Config;
+use progress::Printer;
+
+use pnet::datalink::{EthernetDataLinkChannelIterator, EthernetDataLinkReceiver,
+                     EthernetDataLinkSender, NetworkInterface};
+use pnet::packet::{MutablePacket, Packet};
+use pnet::packet::arp::{ArpOperations, ArpPacket, MutableArpPacket};
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+
+use rips::{self, EthernetChannel};
+use rips::ethernet::MacAddr;
+
+use rx::RxCounter;
+
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+/// Size of an Ethernet frame carrying an ARP packet for IPv4 over Ethernet.
+const ARP_FRAME_SIZE: usize = 14 + 28;
+
+/// Handle to inspect what was sent on a mock channel.
+pub struct MockHandle {
+    counter: RxCounter,
+    captured: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl MockHandle {
+    /// Returns number of frames and bytes sent on the channel so far.
+    pub fn sent(&self) -> (usize, usize) {
+        self.counter.get()
+    }
+
+    /// Returns the frames captured so far.
+    pub fn captured(&self) -> Vec<Vec<u8>> {
+        self.captured.lock().unwrap().clone()
+    }
+
+    pub fn print_summary(&self, printer: &mut Printer) {
+        let (pkgs, bytes) = self.sent();
+        printer.print_message(&format!("Mock backend got {} frames, {} bytes", pkgs, bytes));
+        for frame in self.captured() {
+            let hex = frame.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>();
+            printer.print_message(&hex.join(" "));
+        }
+    }
+}
+
+/// Creates an `EthernetChannel` that never touches the network. Frames sent
+/// on it are counted and the first `config.mock_capture` of them are kept.
+/// ARP requests are answered with `config.dst_mac` so the rips stack can
+/// resolve any address. Reads time out after `read_timeout`, if given.
+pub fn channel(bufsize: usize,
+               read_timeout: Option<Duration>,
+               config: &Config)
+               -> (EthernetChannel, MockHandle) {
+    let (loopback_tx, loopback_rx) = mpsc::channel();
+    let handle = MockHandle {
+        counter: RxCounter::new(),
+        captured: Arc::new(Mutex::new(Vec::new())),
+    };
+    let sender = MockSender {
+        buffer: vec![0; bufsize],
+        counter: handle.counter.clone(),
+        captured: handle.captured.clone(),
+        capture: config.mock_capture,
+        peer_mac: config.dst_mac,
+        loopback: loopback_tx,
+    };
+    let receiver = MockReceiver {
+        buffer: Vec::new(),
+        frames: loopback_rx,
+        read_timeout: read_timeout,
+    };
+    let channel = EthernetChannel {
+        sender: Box::new(sender),
+        write_buffer_size: bufsize,
+        receiver: Box::new(receiver),
+        read_buffer_size: bufsize,
+    };
+    (channel, handle)
+}
+
+/// The interface the mock channel pretends to belong to.
+pub fn interface(name: &str) -> rips::Interface {
+    rips::Interface {
+        name: name.to_owned(),
+        mac: *::SRC_MAC,
+    }
+}
+
+struct MockSender {
+    buffer: Vec<u8>,
+    counter: RxCounter,
+    captured: Arc<Mutex<Vec<Vec<u8>>>>,
+    capture: usize,
+    peer_mac: MacAddr,
+    loopback: Sender<Vec<u8>>,
+}
+
+impl MockSender {
+    fn handle_frame(&self, frame: &[u8]) {
+        self.counter.add(frame.len());
+        if self.capture > 0 {
+            let mut captured = self.captured.lock().unwrap();
+            if captured.len() < self.capture {
+                captured.push(frame.to_vec());
+            }
+        }
+        if let Some(reply) = self.arp_reply(frame) {
+            let _ = self.loopback.send(reply);
+        }
+    }
+
+    /// Builds a reply claiming `peer_mac` if `frame` is an ARP request.
+    fn arp_reply(&self, frame: &[u8]) -> Option<Vec<u8>> {
+        let eth_pkg = match EthernetPacket::new(frame) {
+            Some(pkg) => pkg,
+            None => return None,
+        };
+        if eth_pkg.get_ethertype() != EtherTypes::Arp {
+            return None;
+        }
+        let arp_pkg = match ArpPacket::new(eth_pkg.payload()) {
+            Some(pkg) => pkg,
+            None => return None,
+        };
+        if arp_pkg.get_operation() != ArpOperations::Request {
+            return None;
+        }
+
+        let mut reply = vec![0; ARP_FRAME_SIZE];
+        {
+            let mut reply_eth_pkg = MutableEthernetPacket::new(&mut reply).unwrap();
+            reply_eth_pkg.set_destination(eth_pkg.get_source());
+            reply_eth_pkg.set_source(self.peer_mac);
+            reply_eth_pkg.set_ethertype(EtherTypes::Arp);
+            let mut reply_arp_pkg = MutableArpPacket::new(reply_eth_pkg.payload_mut()).unwrap();
+            reply_arp_pkg.set_hardware_type(arp_pkg.get_hardware_type());
+            reply_arp_pkg.set_protocol_type(arp_pkg.get_protocol_type());
+            reply_arp_pkg.set_hw_addr_len(6);
+            reply_arp_pkg.set_proto_addr_len(4);
+            reply_arp_pkg.set_operation(ArpOperations::Reply);
+            reply_arp_pkg.set_sender_hw_addr(self.peer_mac);
+            reply_arp_pkg.set_sender_proto_addr(arp_pkg.get_target_proto_addr());
+            reply_arp_pkg.set_target_hw_addr(arp_pkg.get_sender_hw_addr());
+            reply_arp_pkg.set_target_proto_addr(arp_pkg.get_sender_proto_addr());
+        }
+        Some(reply)
+    }
+}
+
+impl EthernetDataLinkSender for MockSender {
+    fn build_and_send(&mut self,
+                      num_packets: usize,
+                      packet_size: usize,
+                      func: &mut FnMut(MutableEthernetPacket))
+                      -> Option<io::Result<()>> {
+        let total_size = num_packets * packet_size;
+        if total_size > self.buffer.len() {
+            return None;
+        }
+        let mut buffer = ::std::mem::replace(&mut self.buffer, Vec::new());
+        for chunk in buffer[..total_size].chunks_mut(packet_size) {
+            func(MutableEthernetPacket::new(chunk).unwrap());
+            self.handle_frame(chunk);
+        }
+        self.buffer = buffer;
+        Some(Ok(()))
+    }
+
+    fn send_to(&mut self,
+               packet: &EthernetPacket,
+               _dst: Option<NetworkInterface>)
+               -> Option<io::Result<()>> {
+        self.handle_frame(packet.packet());
+        Some(Ok(()))
+    }
+}
+
+struct MockReceiver {
+    buffer: Vec<u8>,
+    frames: Receiver<Vec<u8>>,
+    read_timeout: Option<Duration>,
+}
+
+impl EthernetDataLinkReceiver for MockReceiver {
+    fn iter<'a>(&'a mut self) -> Box<EthernetDataLinkChannelIterator + 'a> {
+        Box::new(MockChannelIterator { receiver: self })
+    }
+}
+
+struct MockChannelIterator<'a> {
+    receiver: &'a mut MockReceiver,
+}
+
+impl<'a> EthernetDataLinkChannelIterator<'a> for MockChannelIterator<'a> {
+    fn next(&mut self) -> io::Result<EthernetPacket> {
+        let frame = match self.receiver.read_timeout {
+            Some(timeout) => self.receiver.frames.recv_timeout(timeout),
+            None => self.receiver.frames.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match frame {
+            Ok(frame) => {
+                self.receiver.buffer = frame;
+                Ok(EthernetPacket::new(&self.receiver.buffer).unwrap())
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                Err(io::Error::new(io::ErrorKind::TimedOut, "No frame within the timeout"))
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "Mock sender is gone"))
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use results::JsonObject;
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Fraction of the sent packets allowed to still be queued, and not yet
+/// counted by the NIC, when a measurement stops.
+const QUEUED_TOLERANCE: f64 = 0.01;
+
+/// Transmit counters of a network interface, as kept by its driver.
+#[derive(Debug, Clone, Copy)]
+pub struct NicCounters {
+    pub tx_packets: u64,
+    pub tx_bytes: u64,
+    pub tx_dropped: u64,
+}
+
+impl NicCounters {
+    /// Reads the counters of `iface` from sysfs. Returns `None` if the
+    /// interface has no statistics there, like the mock interface.
+    pub fn read(iface: &str) -> Option<Self> {
+        let dir = Path::new("/sys/class/net").join(iface).join("statistics");
+        let counter = |name: &str| read_counter(&dir.join(name));
+        match (counter("tx_packets"), counter("tx_bytes"), counter("tx_dropped")) {
+            (Some(tx_packets), Some(tx_bytes), Some(tx_dropped)) => {
+                Some(NicCounters {
+                    tx_packets: tx_packets,
+                    tx_bytes: tx_bytes,
+                    tx_dropped: tx_dropped,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Counters between `start` and `self`.
+    pub fn since(&self, start: &NicCounters) -> NicCounters {
+        NicCounters {
+            tx_packets: self.tx_packets.wrapping_sub(start.tx_packets),
+            tx_bytes: self.tx_bytes.wrapping_sub(start.tx_bytes),
+            tx_dropped: self.tx_dropped.wrapping_sub(start.tx_dropped),
+        }
+    }
+
+    pub fn add(&mut self, other: &NicCounters) {
+        self.tx_packets += other.tx_packets;
+        self.tx_bytes += other.tx_bytes;
+        self.tx_dropped += other.tx_dropped;
+    }
+
+    /// Describes how the counters disagree with a benchmark claiming to have
+    /// sent `packets`, if they do. The NIC may count more frames than
+    /// packets, since large IPv4 packets are fragmented and the stack sends
+    /// ARP on its own, but fewer only by the frames still queued when the
+    /// measurement stopped.
+    pub fn discrepancy(&self, packets: usize) -> Option<String> {
+        if self.tx_dropped > 0 {
+            Some(format!("{} frames dropped by the NIC", self.tx_dropped))
+        } else if (self.tx_packets as f64) < packets as f64 * (1.0 - QUEUED_TOLERANCE) {
+            Some(format!("{} fewer frames sent by the NIC than reported",
+                         packets as u64 - self.tx_packets))
+        } else {
+            None
+        }
+    }
+
+    pub fn to_json(&self, packets: usize) -> JsonObject {
+        JsonObject::new()
+            .number("tx_packets", self.tx_packets)
+            .number("tx_bytes", self.tx_bytes)
+            .number("tx_dropped", self.tx_dropped)
+            .bool("discrepancy", self.discrepancy(packets).is_some())
+    }
+}
+
+/// Reads a file holding a single number, like the ones in sysfs.
+pub fn read_counter(path: &Path) -> Option<u64> {
+    let mut content = String::new();
+    match File::open(path).and_then(|mut file| file.read_to_string(&mut content)) {
+        Ok(_) => content.trim().parse().ok(),
+        Err(_) => None,
+    }
+}
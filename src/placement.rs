@@ -0,0 +1,99 @@
+//! Pinning of benchmark threads to CPUs and realtime scheduling, to make
+//! results reproducible between runs.
+
+use bench;
+use results::JsonObject;
+
+use std::io;
+
+/// Where and how the benchmark threads run.
+#[derive(Debug, Clone)]
+pub struct Placement {
+    /// CPUs to pin threads to. Thread `i` runs on `cpus[i % cpus.len()]`.
+    /// Empty means no pinning.
+    pub cpus: Vec<usize>,
+    /// `SCHED_FIFO` priority, or `None` to keep the default policy.
+    pub fifo_priority: Option<i32>,
+}
+
+impl Placement {
+    pub fn new() -> Self {
+        Placement {
+            cpus: Vec::new(),
+            fifo_priority: None,
+        }
+    }
+
+    /// Applies the placement of benchmark thread `index` to the calling
+    /// thread.
+    pub fn apply(&self, index: usize) -> io::Result<()> {
+        if !self.cpus.is_empty() {
+            let cpu = self.cpus[index % self.cpus.len()];
+            if let Err(e) = set_affinity(cpu) {
+                let what = format!("Unable to pin thread to CPU {}", cpu);
+                return Err(bench::context(e, &what));
+            }
+        }
+        if let Some(priority) = self.fifo_priority {
+            if let Err(e) = set_fifo(priority) {
+                let what = format!("Unable to set SCHED_FIFO priority {}", priority);
+                return Err(bench::context(e, &what));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> JsonObject {
+        let cpus = self.cpus.iter().map(|cpu| cpu.to_string()).collect::<Vec<_>>();
+        let json = JsonObject::new().string("cpus", cpus.join(","));
+        match self.fifo_priority {
+            Some(priority) => json.string("sched", "fifo").number("priority", priority),
+            None => json.string("sched", "other"),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_affinity(cpu: usize) -> io::Result<()> {
+    use libc;
+    use std::mem;
+
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+    // cpu_set_t is a plain bit mask made up of unsigned longs.
+    let word_bits = mem::size_of::<libc::c_ulong>() * 8;
+    if cpu >= mem::size_of::<libc::cpu_set_t>() * 8 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "CPU number too high"));
+    }
+    unsafe {
+        let words = &mut set as *mut libc::cpu_set_t as *mut libc::c_ulong;
+        *words.offset((cpu / word_bits) as isize) |= 1 << (cpu % word_bits);
+    }
+    let result = unsafe { libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_fifo(priority: i32) -> io::Result<()> {
+    use libc;
+
+    let param = libc::sched_param { sched_priority: priority };
+    if unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO, &param) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(_cpu: usize) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "Only supported on Linux"))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_fifo(_priority: i32) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "Only supported on Linux"))
+}
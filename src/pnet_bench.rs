@@ -1,46 +1,104 @@
 use Config;
-use progress;
+use bench::{self, Benchmark, SendFn};
+use progress::Printer;
+
+use pnet::datalink::EthernetDataLinkSender;
+use pnet::packet::Packet;
 
 use rips::EthernetChannel;
 
-use std::time::Instant;
-
-pub fn bench(channel: EthernetChannel, config: &Config) {
-    let mut sender = channel.sender;
-    let mut printer = progress::Printer::new();
-
-    printer.print_title("Raw pnet datalink sending");
-    for packets_per_call in vec![1, 10, 100, 1000] {
-        for bytes_per_packet in packet_sizes(config) {
-            printer.print_line_description(&format!("Sending {}x{} bytes",
-                                                    packets_per_call,
-                                                    bytes_per_packet));
-            let mut pkgs = 0;
-            let mut bytes = 0;
-            let mut next_print_second = 1;
-            let timer = Instant::now();
-            loop {
-                sender.build_and_send(packets_per_call, bytes_per_packet, &mut |_packet| {})
-                    .expect("Too small buffer")
-                    .expect("Unable to send");
-                pkgs += packets_per_call;
-                bytes += packets_per_call * bytes_per_packet;
-
-                let elapsed = timer.elapsed();
-                if elapsed.as_secs() >= next_print_second {
-                    printer.print_statistics(pkgs, bytes, elapsed);
-                    next_print_second += 1;
-                }
-                if elapsed > config.duration {
-                    break;
+use rx::{self, RxCounter, RxThread};
+use wire::Encapsulation;
+
+use std::io;
+use std::sync::atomic::Ordering;
+
+pub struct PnetBench {
+    sender: Option<Box<EthernetDataLinkSender>>,
+    write_buffer_size: usize,
+}
+
+impl Benchmark for PnetBench {
+    fn setup(channel: EthernetChannel,
+             _config: &Config,
+             _printer: &mut Printer)
+             -> io::Result<Self> {
+        Ok(PnetBench {
+            sender: Some(channel.sender),
+            write_buffer_size: channel.write_buffer_size,
+        })
+    }
+
+    fn title(&self) -> &'static str {
+        "Raw pnet datalink sending"
+    }
+
+    fn encapsulation(&self) -> Encapsulation {
+        Encapsulation::Frame
+    }
+
+    fn batches(&self, config: &Config) -> Vec<usize> {
+        bench::batches(config, &[1, 10, 100, 1000])
+    }
+
+    fn sizes(&self, config: &Config, printer: &mut Printer) -> Vec<usize> {
+        packet_sizes(config, printer)
+    }
+
+    /// A batch is built in the write buffer of the channel, so it has to
+    /// fit there, see `--netbuf`.
+    fn max_bytes_per_call(&self) -> Option<usize> {
+        Some(self.write_buffer_size)
+    }
+
+    /// There is only one datalink sender, so only one thread can send.
+    fn sender(&mut self, _config: &Config, _thread: usize) -> io::Result<Option<SendFn>> {
+        Ok(self.sender.take().map(|mut sender| {
+            Box::new(move |packets_per_call: usize, bytes_per_packet: usize| {
+                let total_bytes = packets_per_call * bytes_per_packet;
+                match sender.build_and_send(packets_per_call, bytes_per_packet, &mut |_| {}) {
+                    None => {
+                        Err(io::Error::new(io::ErrorKind::Other,
+                                           "Batch doesn't fit in the write buffer"))
+                    }
+                    Some(Err(e)) => Err(e),
+                    Some(Ok(())) => Ok(Some((packets_per_call, total_bytes))),
                 }
+            }) as SendFn
+        }))
+    }
+}
+
+pub fn bench_rx(channel: EthernetChannel,
+                config: &Config,
+                printer: &mut Printer)
+                -> io::Result<()> {
+    let mut receiver = channel.receiver;
+    let counter = RxCounter::new();
+
+    printer.print_title("Raw pnet datalink receiving");
+    printer.set_encapsulation(Encapsulation::Frame);
+    printer.start_case("Receiving all frames", 1, 0)?;
+    let thread_counter = counter.clone();
+    let rx_thread = RxThread::spawn(move |running| {
+        let mut rx_iter = receiver.iter();
+        while running.load(Ordering::Relaxed) {
+            match rx_iter.next() {
+                Ok(packet) => thread_counter.add(packet.packet().len()),
+                Err(ref e) if rx::is_timeout(e) => (),
+                Err(e) => return Err(e),
             }
-            printer.end_line();
         }
-    }
+        Ok(())
+    });
+    let measured = rx::measure(printer, &counter, config);
+    rx_thread.stop()?;
+    measured?;
+    printer.end_case();
+    Ok(())
 }
 
-fn packet_sizes(config: &Config) -> Vec<usize> {
+fn packet_sizes(config: &Config, printer: &mut Printer) -> Vec<usize> {
     let mut sizes = Vec::new();
     if config.size_min {
         sizes.push(42);
@@ -48,5 +106,9 @@ fn packet_sizes(config: &Config) -> Vec<usize> {
     if config.size_mtu {
         sizes.push(config.mtu + 14);
     }
+    if config.size_max {
+        printer.print_message("Skipping the max size, Ethernet frames can't exceed the MTU");
+    }
+    sizes.extend(config.sizes.iter().map(|size| size + 14));
     sizes
 }
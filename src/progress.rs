@@ -1,53 +1,434 @@
+use Config;
+use args::{Backend, Output};
+use bench;
+use control::ControlClient;
+use cpu::CpuUsage;
+use histogram::Histogram;
+use latency::Latency;
+use nic::{self, NicCounters};
+use results::{JsonObject, Record, Summary};
+use wire::Encapsulation;
+
+use std::fs::File;
 use std::io::{self, Write};
 use std::iter;
-use std::time::Duration;
+use std::mem;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
 pub struct Printer {
     line_description: String,
+    status: String,
     last_print_length: usize,
+    console: Box<Write>,
+    record_sinks: Vec<Box<Write>>,
+    timeseries: Option<File>,
+    last_sample: (usize, usize, f64),
+    config: JsonObject,
+    record: Record,
+    repetitions: Vec<Record>,
+    /// Summarized record of every finished case.
+    results: Vec<Record>,
+    case_details: Vec<(String, JsonObject)>,
+    control: Option<ControlClient>,
+    cpu_start: Option<CpuUsage>,
+    /// Interface the NIC counters are read from, `None` with the mock
+    /// backend where nothing goes through a real interface.
+    iface: Option<String>,
+    receiving: bool,
+    nic_start: Option<NicCounters>,
+    discarding: bool,
+    /// Whether the packets of the current case have different sizes.
+    mixed_sizes: bool,
+    encapsulation: Option<Encapsulation>,
+    mtu: usize,
+    /// Speed of the interface in Mbit/s, if it's known.
+    link_speed: Option<u64>,
 }
 
 impl Printer {
-    pub fn new() -> Self {
-        Printer {
+    /// Fails if a results or time series file can't be created.
+    pub fn new(config: &Config) -> io::Result<Self> {
+        let mut record_sinks: Vec<Box<Write>> = Vec::new();
+        let console: Box<Write> = match config.output {
+            Output::Human => Box::new(io::stdout()),
+            Output::Json => {
+                record_sinks.push(Box::new(io::stdout()));
+                Box::new(io::stderr())
+            }
+            Output::Quiet => Box::new(io::sink()),
+        };
+        if let Some(ref path) = config.results_path {
+            record_sinks.push(Box::new(create_file(path, "results")?));
+        }
+        let timeseries = match config.timeseries_path {
+            Some(ref path) => {
+                let mut file = create_file(path, "time series")?;
+                writeln!(file,
+                         "suite,packets_per_call,bytes_per_packet,elapsed,packets,bytes,pps,\
+                          bytes_per_sec,interval_pps,interval_bytes_per_sec,repetition")?;
+                Some(file)
+            }
+            None => None,
+        };
+        let iface = match config.backend {
+            Backend::Pnet => Some(config.iface.name.clone()),
+            Backend::Mock => None,
+        };
+        Ok(Printer {
             line_description: String::new(),
+            status: String::new(),
             last_print_length: 0,
-        }
+            console: console,
+            record_sinks: record_sinks,
+            timeseries: timeseries,
+            last_sample: (0, 0, 0.0),
+            config: config.to_json(),
+            record: Record {
+                suite: String::new(),
+                packets_per_call: 0,
+                bytes_per_packet: 0,
+                duration_secs: 0.0,
+                packets: 0,
+                bytes: 0,
+                invalid_tx: 0,
+                latency: None,
+                delivered: None,
+                cpu: None,
+                nic: None,
+                wire_bytes: None,
+                send_latency: None,
+            },
+            repetitions: Vec::new(),
+            results: Vec::new(),
+            case_details: Vec::new(),
+            control: None,
+            cpu_start: None,
+            iface: iface.clone(),
+            receiving: false,
+            nic_start: None,
+            discarding: false,
+            mixed_sizes: false,
+            encapsulation: None,
+            mtu: config.mtu,
+            link_speed: iface.as_ref().and_then(|iface| read_link_speed(iface)),
+        })
+    }
+
+    /// Makes every case announce itself to, and collect the delivered
+    /// counts from, a sink at the other end of `control`.
+    pub fn set_control(&mut self, control: ControlClient) {
+        self.control = Some(control);
     }
 
     pub fn print_title(&mut self, title: &str) {
+        self.record.suite = title.to_owned();
+        self.encapsulation = None;
+        self.discarding = false;
         self.println(&format!("== {} ==", title)).unwrap();
     }
 
-    pub fn print_line_description(&mut self, desc: &str) {
+    /// Makes the following cases of the suite account for the bytes put on
+    /// the wire, given what the counted bytes are the payload of.
+    pub fn set_encapsulation(&mut self, encapsulation: Encapsulation) {
+        self.encapsulation = Some(encapsulation);
+    }
+
+    /// Marks the following cases of the suite as sending into a channel
+    /// discarding every frame. The time spent per packet is reported, and
+    /// the NIC and its link speed are left out since nothing reaches them.
+    pub fn set_discarding(&mut self) {
+        self.discarding = true;
+    }
+
+    /// Prints a line of informational text that is not part of the results.
+    pub fn print_message(&mut self, message: &str) {
+        self.println(message).unwrap();
+    }
+
+    /// Starts a new case. Fails if it can't be announced to the sink.
+    pub fn start_case(&mut self,
+                      desc: &str,
+                      packets_per_call: usize,
+                      bytes_per_packet: usize)
+                      -> io::Result<()> {
         self.line_description = desc.to_owned();
+        self.status.clear();
+        self.case_details.clear();
+        self.record.packets_per_call = packets_per_call;
+        self.record.bytes_per_packet = bytes_per_packet;
+        self.repetitions.clear();
+        self.reset_record();
+        self.receiving = false;
+        self.mixed_sizes = false;
+        if let Some(ref mut control) = self.control {
+            control.announce(&self.record.suite, packets_per_call, bytes_per_packet)
+                .map_err(control_failed)?;
+        }
+        Ok(())
+    }
+
+    /// Marks the current case as sending packets of different sizes, making
+    /// it report the average bytes per packet.
+    pub fn set_mixed_sizes(&mut self) {
+        self.mixed_sizes = true;
+    }
+
+    /// Marks the current case as counting received packets, which the
+    /// transmit counters of the NIC say nothing about.
+    pub fn set_receiving(&mut self) {
+        self.receiving = true;
+    }
+
+    /// Called by the `Meter` when the measurement of a repetition starts.
+    pub fn start_measurement(&mut self) -> io::Result<()> {
+        self.cpu_start = CpuUsage::now();
+        if !self.receiving && !self.discarding {
+            self.nic_start = self.iface.as_ref().and_then(|iface| NicCounters::read(iface));
+        }
+        if let Some(ref mut control) = self.control {
+            control.start().map_err(control_failed)?;
+        }
+        Ok(())
+    }
+
+    /// Called by the `Meter` when the measurement of a repetition is done.
+    pub fn stop_measurement(&mut self) -> io::Result<()> {
+        if let (Some(start), Some(now)) = (self.cpu_start.take(), CpuUsage::now()) {
+            self.record.cpu = Some(now.since(&start));
+        }
+        if let Some(start) = self.nic_start.take() {
+            let now = self.iface.as_ref().and_then(|iface| NicCounters::read(iface));
+            self.record.nic = now.map(|now| now.since(&start));
+        }
+        if let Some(ref mut control) = self.control {
+            self.record.delivered = Some(control.stop().map_err(control_failed)?);
+        }
+        Ok(())
     }
 
     pub fn print_statistics(&mut self, pkgs: usize, bytes: usize, duration: Duration) {
-        let text = self.build_statistics_string(pkgs, bytes, duration);
+        self.record.duration_secs = duration_secs(duration);
+        self.record.packets = pkgs;
+        self.record.bytes = bytes;
+        let mtu = self.mtu;
+        self.record.wire_bytes = self.encapsulation.map(|e| e.wire_bytes(pkgs, bytes, mtu));
+        self.write_sample().unwrap();
+        let text = self.build_statistics_string();
         self.print(&text).unwrap();
     }
 
-    pub fn end_line(&mut self) {
-        println!("");
+    pub fn set_invalid_tx(&mut self, invalid_tx: usize) {
+        self.record.invalid_tx = invalid_tx;
+    }
+
+    /// Sets extra text shown after the rate on the statistics line.
+    pub fn set_status(&mut self, status: &str) {
+        self.status = status.to_owned();
+    }
+
+    /// Adds an object under `key` to the record of the current case.
+    pub fn add_case_details(&mut self, key: &str, details: JsonObject) {
+        self.case_details.push((key.to_owned(), details));
+    }
+
+    pub fn set_latency(&mut self, latency: Latency) {
+        self.record.latency = Some(latency);
+    }
+
+    pub fn set_send_latency(&mut self, send_latency: Histogram) {
+        self.record.send_latency = Some(send_latency);
+    }
+
+    /// Finishes one repetition of the current case.
+    pub fn end_repetition(&mut self) {
+        self.end_line();
+        self.repetitions.push(self.record.clone());
+        self.reset_record();
+    }
+
+    /// Finishes the current case and emits its record, summarizing all
+    /// repetitions.
+    pub fn end_case(&mut self) {
+        if self.repetitions.is_empty() {
+            self.end_repetition();
+        }
+        let pps = self.repetitions.iter().map(|r| r.pps()).collect::<Vec<f64>>();
+        let summary = Summary::new(&pps);
+        if self.repetitions.len() > 1 {
+            let text = format!("{}: {:.0} pps mean, {:.0} min, {:.0} max, {:.0} stddev",
+                               self.line_description,
+                               summary.mean,
+                               summary.min,
+                               summary.max,
+                               summary.stddev);
+            self.println(&text).unwrap();
+        }
+        let total = Record::total(&self.repetitions);
+        if let Some(ref rtt) = total.latency {
+            let text = format!("{}: rtt {}", self.line_description, rtt.status());
+            self.println(&text).unwrap();
+        }
+        if let Some((delivered_pkgs, _)) = total.delivered {
+            let text = format!("{}: {:.0} pps delivered, {:.2}% of sent",
+                               self.line_description,
+                               delivered_pkgs as f64 / total.duration_secs,
+                               delivered_pkgs as f64 * 100.0 / total.packets as f64);
+            self.println(&text).unwrap();
+        }
+        if let Some(ref cpu) = total.cpu {
+            if total.packets > 0 {
+                let (user, system) = cpu.micros_per_packet(total.packets);
+                let text = format!("{}: {:.3} µs CPU per packet ({:.3} user, {:.3} system), {} \
+                                    voluntary and {} involuntary context switches",
+                                   self.line_description,
+                                   user + system,
+                                   user,
+                                   system,
+                                   cpu.voluntary_switches,
+                                   cpu.involuntary_switches);
+                self.println(&text).unwrap();
+            }
+        }
+        if let Some(ref send_latency) = total.send_latency {
+            let text = format!("{}: send call {}",
+                               self.line_description,
+                               send_latency.status());
+            self.println(&text).unwrap();
+        }
+        if self.mixed_sizes && total.packets > 0 {
+            let text = format!("{}: {:.1} bytes per packet on average",
+                               self.line_description,
+                               total.average_bytes_per_packet());
+            self.println(&text).unwrap();
+        }
+        if self.discarding && total.packets > 0 {
+            let text = format!("{}: {:.1} ns per packet",
+                               self.line_description,
+                               total.ns_per_packet());
+            self.println(&text).unwrap();
+        }
+        if let Some(ref nic) = total.nic {
+            let mut text = format!("{}: NIC sent {} frames, {} bytes, {} dropped",
+                                   self.line_description,
+                                   nic.tx_packets,
+                                   nic.tx_bytes,
+                                   nic.tx_dropped);
+            if let Some(discrepancy) = nic.discrepancy(total.packets) {
+                text.push_str(&format!(" - WARNING: {}", discrepancy));
+            }
+            self.println(&text).unwrap();
+        }
+        let mut json = total.to_json(self.config.clone())
+            .number("repetitions", self.repetitions.len())
+            .object("pps_summary", summary.to_json());
+        if self.discarding && total.packets > 0 {
+            json = json.number("ns_per_packet", total.ns_per_packet());
+        }
+        if self.mixed_sizes && total.packets > 0 {
+            json = json.number("average_bytes_per_packet", total.average_bytes_per_packet());
+        }
+        if let Some(utilization) = self.line_rate_utilization(&total) {
+            json = json.number("link_speed_mbps", self.link_speed.unwrap())
+                .number("line_rate_utilization", utilization);
+        }
+        for &(ref key, ref details) in &self.case_details {
+            json = json.object(key, details.clone());
+        }
+        let json = json.to_string();
+        for sink in &mut self.record_sinks {
+            writeln!(sink, "{}", json).unwrap();
+            sink.flush().unwrap();
+        }
+        self.results.push(total);
+    }
+
+    /// The records of all cases finished so far.
+    pub fn results(&self) -> &[Record] {
+        &self.results
+    }
+
+    /// Returns the records of all cases finished so far.
+    pub fn into_results(self) -> Vec<Record> {
+        self.results
+    }
+
+    fn reset_record(&mut self) {
+        self.record.duration_secs = 0.0;
+        self.record.packets = 0;
+        self.record.bytes = 0;
+        self.record.invalid_tx = 0;
+        self.record.latency = None;
+        self.record.delivered = None;
+        self.record.cpu = None;
+        self.record.nic = None;
+        self.record.wire_bytes = None;
+        self.record.send_latency = None;
+        self.last_sample = (0, 0, 0.0);
+    }
+
+    fn end_line(&mut self) {
+        writeln!(self.console, "").unwrap();
         self.last_print_length = 0;
     }
 
-    fn build_statistics_string(&self,
-                               mut pkgs: usize,
-                               mut bytes: usize,
-                               duration: Duration)
-                               -> String {
-        let passed_secs = duration.as_secs() as f64 +
-                          (duration.subsec_nanos() as f64) / 1_000_000_000f64;
-        pkgs = (pkgs as f64 / passed_secs) as usize;
-        bytes = (bytes as f64 / passed_secs) as usize;
+    /// Appends the current statistics, cumulative and for the interval since
+    /// the previous sample, to the time series file.
+    fn write_sample(&mut self) -> io::Result<()> {
+        let record = &self.record;
+        let (last_pkgs, last_bytes, last_secs) = self.last_sample;
+        self.last_sample = (record.packets, record.bytes, record.duration_secs);
+        if let Some(ref mut file) = self.timeseries {
+            let interval_secs = record.duration_secs - last_secs;
+            let interval_pps = (record.packets - last_pkgs) as f64 / interval_secs;
+            let interval_bytes = (record.bytes - last_bytes) as f64 / interval_secs;
+            writeln!(file,
+                     "\"{}\",{},{},{},{},{},{},{},{},{},{}",
+                     record.suite,
+                     record.packets_per_call,
+                     record.bytes_per_packet,
+                     record.duration_secs,
+                     record.packets,
+                     record.bytes,
+                     record.pps(),
+                     record.bytes_per_sec(),
+                     interval_pps,
+                     interval_bytes,
+                     self.repetitions.len())?;
+            file.flush()?;
+        }
+        Ok(())
+    }
+
+    fn build_statistics_string(&self) -> String {
+        let pkgs = self.record.pps() as usize;
+        let bytes = self.record.bytes_per_sec() as usize;
         let (scaled_bytes, bytes_suffix) = bytes_to_human(bytes);
-        format!("{}: {} {}B/s - {} pps",
-                self.line_description,
-                scaled_bytes,
-                bytes_suffix,
-                pkgs)
+        let mut text = format!("{}: {} {}B/s - {} pps",
+                               self.line_description,
+                               scaled_bytes,
+                               bytes_suffix,
+                               pkgs);
+        if let Some(wire_bits) = self.record.wire_bits_per_sec() {
+            text.push_str(&format!(" - {:.1} Mbit/s on wire", wire_bits / 1_000_000f64));
+            if let Some(utilization) = self.line_rate_utilization(&self.record) {
+                text.push_str(&format!(", {:.1}% of line rate", utilization * 100.0));
+            }
+        }
+        if !self.status.is_empty() {
+            text.push_str(" - ");
+            text.push_str(&self.status);
+        }
+        text
+    }
+
+    /// Fraction of the line rate of the interface that `record` used.
+    fn line_rate_utilization(&self, record: &Record) -> Option<f64> {
+        match (record.wire_bits_per_sec(), self.link_speed) {
+            _ if self.discarding => None,
+            (Some(wire_bits), Some(speed)) => Some(wire_bits / (speed as f64 * 1_000_000f64)),
+            _ => None,
+        }
     }
 
     fn println(&mut self, line: &str) -> io::Result<()> {
@@ -57,7 +438,7 @@ impl Printer {
     }
 
     fn print(&mut self, line: &str) -> io::Result<()> {
-        let mut stream = io::stdout();
+        let stream = &mut self.console;
         stream.write_all(line.as_bytes())?;
 
         let length = line.chars().count();
@@ -74,6 +455,106 @@ impl Printer {
     }
 }
 
+/// Keeps track of time and counters for one repetition of a benchmark case.
+/// Takes care of the warmup, printing statistics once per second and telling
+/// when the configured duration has passed.
+pub struct Meter {
+    warmup: Duration,
+    duration: Duration,
+    timer: Instant,
+    warming_up: bool,
+    started: bool,
+    next_print_second: u64,
+    pkgs: usize,
+    bytes: usize,
+    send_latency: Histogram,
+}
+
+impl Meter {
+    pub fn new(config: &Config) -> Self {
+        Meter {
+            warmup: config.warmup,
+            duration: config.duration,
+            timer: Instant::now(),
+            warming_up: config.warmup > Duration::new(0, 0),
+            started: false,
+            next_print_second: 1,
+            pkgs: 0,
+            bytes: 0,
+            send_latency: Histogram::new(),
+        }
+    }
+
+    pub fn add(&mut self, pkgs: usize, bytes: usize) {
+        self.pkgs += pkgs;
+        self.bytes += bytes;
+    }
+
+    /// Records how long a send call took, unless warming up.
+    pub fn add_send_latency(&mut self, duration: Duration) {
+        if self.started {
+            self.send_latency.record(duration);
+        }
+    }
+
+    pub fn warming_up(&self) -> bool {
+        self.warming_up
+    }
+
+    /// Returns false when the measurement is done, after printing the final
+    /// statistics. Everything counted during the warmup is discarded. Fails
+    /// if the sink can't be told about the measurement.
+    pub fn tick(&mut self, printer: &mut Printer) -> io::Result<bool> {
+        let elapsed = self.timer.elapsed();
+        if !self.started {
+            if self.warming_up && elapsed < self.warmup {
+                return Ok(true);
+            }
+            self.warming_up = false;
+            self.started = true;
+            printer.start_measurement()?;
+            self.timer = Instant::now();
+            self.pkgs = 0;
+            self.bytes = 0;
+            return Ok(true);
+        }
+        if elapsed > self.duration {
+            printer.stop_measurement()?;
+            if !self.send_latency.is_empty() {
+                printer.set_send_latency(mem::replace(&mut self.send_latency, Histogram::new()));
+            }
+            printer.print_statistics(self.pkgs, self.bytes, elapsed);
+            return Ok(false);
+        }
+        if elapsed.as_secs() >= self.next_print_second {
+            printer.print_statistics(self.pkgs, self.bytes, elapsed);
+            self.next_print_second += 1;
+        }
+        Ok(true)
+    }
+}
+
+fn control_failed(e: io::Error) -> io::Error {
+    bench::context(e, "Control connection to the sink failed")
+}
+
+/// Reads the speed of `iface` in Mbit/s. Interfaces without a known speed,
+/// like virtual ones, report -1 or nothing at all.
+fn read_link_speed(iface: &str) -> Option<u64> {
+    let path = Path::new("/sys/class/net").join(iface).join("speed");
+    nic::read_counter(&path).and_then(|speed| if speed > 0 { Some(speed) } else { None })
+}
+
+fn create_file(path: &Path, what: &str) -> io::Result<File> {
+    File::create(path).map_err(|e| {
+        bench::context(e, &format!("Unable to create {} file {}", what, path.display()))
+    })
+}
+
+pub fn duration_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + (duration.subsec_nanos() as f64) / 1_000_000_000f64
+}
+
 fn bytes_to_human(mut bytes: usize) -> (usize, &'static str) {
     static SIZE_SUFFIXES: [&'static str; 6] = ["", "ki", "Mi", "Gi", "Ti", "Pi"];
     for i in 0..SIZE_SUFFIXES.len() {
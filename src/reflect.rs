@@ -0,0 +1,46 @@
+use Config;
+use bench;
+use progress::Printer;
+use rips::EthernetChannel;
+use rips::udp::UdpSocket;
+use rips_bench;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Echoes every datagram received on `config.src` back to its sender, until
+/// the process is killed or sending or receiving fails.
+pub fn run(channel: EthernetChannel, config: &Config, printer: &mut Printer) -> io::Result<()> {
+    let stack = rips_bench::create_stack(channel, config);
+
+    let stack = Arc::new(Mutex::new(stack));
+    let mut socket = UdpSocket::bind(stack, config.src)?;
+
+    printer.print_title("Rips UDP reflector");
+    printer.start_case(&format!("Reflecting datagrams sent to {}", config.src), 1, 0)?;
+
+    let mut buffer = vec![0; 65535];
+    let mut pkgs = 0;
+    let mut bytes = 0;
+    let mut next_print_second = 1;
+    let timer = Instant::now();
+    loop {
+        match socket.recv_from(&mut buffer) {
+            Ok((size, SocketAddr::V4(src))) => {
+                socket.send_to(&buffer[..size], src)
+                    .map_err(|e| bench::context(e, "Error while sending to the network"))?;
+                pkgs += 1;
+                bytes += size;
+            }
+            Ok((_size, SocketAddr::V6(_src))) => (),
+            Err(e) => return Err(bench::context(e, "Error while receiving from the network")),
+        }
+
+        let elapsed = timer.elapsed();
+        if elapsed.as_secs() >= next_print_second {
+            printer.print_statistics(pkgs, bytes, elapsed);
+            next_print_second = elapsed.as_secs() + 1;
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use cpu::CpuUsage;
+use histogram::Histogram;
+use latency::Latency;
+use nic::NicCounters;
+
+use std::fmt;
+
+/// Minimal builder for a flat JSON object. Values are rendered when added.
+#[derive(Clone)]
+pub struct JsonObject {
+    fields: Vec<(String, String)>,
+}
+
+impl JsonObject {
+    pub fn new() -> Self {
+        JsonObject { fields: Vec::new() }
+    }
+
+    pub fn string<V: fmt::Display>(self, key: &str, value: V) -> Self {
+        let value = escape(&value.to_string());
+        self.raw(key, value)
+    }
+
+    /// Adds a number. Infinite and NaN floats, e.g. rates of cases that sent
+    /// nothing, have no JSON representation and become `null`.
+    pub fn number<V: fmt::Display>(self, key: &str, value: V) -> Self {
+        let value = match value.to_string().as_str() {
+            "inf" | "-inf" | "NaN" => "null".to_owned(),
+            value => value.to_owned(),
+        };
+        self.raw(key, value)
+    }
+
+    pub fn bool(self, key: &str, value: bool) -> Self {
+        self.raw(key, value.to_string())
+    }
+
+    pub fn object(self, key: &str, value: JsonObject) -> Self {
+        self.raw(key, value.to_string())
+    }
+
+    fn raw(mut self, key: &str, value: String) -> Self {
+        self.fields.push((escape(key), value));
+        self
+    }
+}
+
+impl fmt::Display for JsonObject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{")?;
+        for (i, &(ref key, ref value)) in self.fields.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}:{}", key, value)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// Result of running one benchmark case.
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub suite: String,
+    pub packets_per_call: usize,
+    pub bytes_per_packet: usize,
+    pub duration_secs: f64,
+    pub packets: usize,
+    pub bytes: usize,
+    pub invalid_tx: usize,
+    pub latency: Option<Latency>,
+    /// Packets and bytes the sink got, when run against one.
+    pub delivered: Option<(usize, usize)>,
+    /// CPU used by the whole process while measuring.
+    pub cpu: Option<CpuUsage>,
+    /// What the NIC counted as sent while measuring.
+    pub nic: Option<NicCounters>,
+    /// Bytes put on the wire, including headers and Ethernet framing.
+    pub wire_bytes: Option<usize>,
+    /// How long every send call took, if `Config::send_latency` is set.
+    pub send_latency: Option<Histogram>,
+}
+
+impl Record {
+    /// Sums up the repetitions of one case into a single record.
+    pub fn total(repetitions: &[Record]) -> Record {
+        let mut total = repetitions[0].clone();
+        for record in &repetitions[1..] {
+            total.duration_secs += record.duration_secs;
+            total.packets += record.packets;
+            total.bytes += record.bytes;
+            total.invalid_tx += record.invalid_tx;
+            if let Some(ref latency) = record.latency {
+                match total.latency {
+                    Some(ref mut total_latency) => total_latency.merge(latency),
+                    None => total.latency = Some(latency.clone()),
+                }
+            }
+            if let Some((packets, bytes)) = record.delivered {
+                let (total_packets, total_bytes) = total.delivered.unwrap_or((0, 0));
+                total.delivered = Some((total_packets + packets, total_bytes + bytes));
+            }
+            if let Some(ref cpu) = record.cpu {
+                match total.cpu {
+                    Some(ref mut total_cpu) => total_cpu.add(cpu),
+                    None => total.cpu = Some(*cpu),
+                }
+            }
+            if let Some(ref send_latency) = record.send_latency {
+                match total.send_latency {
+                    Some(ref mut total_send_latency) => total_send_latency.merge(send_latency),
+                    None => total.send_latency = Some(send_latency.clone()),
+                }
+            }
+            if let Some(wire_bytes) = record.wire_bytes {
+                total.wire_bytes = Some(total.wire_bytes.unwrap_or(0) + wire_bytes);
+            }
+            if let Some(ref nic) = record.nic {
+                match total.nic {
+                    Some(ref mut total_nic) => total_nic.add(nic),
+                    None => total.nic = Some(*nic),
+                }
+            }
+        }
+        total
+    }
+
+    pub fn pps(&self) -> f64 {
+        self.packets as f64 / self.duration_secs
+    }
+
+    pub fn bytes_per_sec(&self) -> f64 {
+        self.bytes as f64 / self.duration_secs
+    }
+
+    pub fn average_bytes_per_packet(&self) -> f64 {
+        self.bytes as f64 / self.packets as f64
+    }
+
+    /// Time spent per packet, in nanoseconds.
+    pub fn ns_per_packet(&self) -> f64 {
+        self.duration_secs * 1_000_000_000f64 / self.packets as f64
+    }
+
+    pub fn wire_bits_per_sec(&self) -> Option<f64> {
+        self.wire_bytes.map(|wire_bytes| wire_bytes as f64 * 8.0 / self.duration_secs)
+    }
+
+    pub fn to_json(&self, config: JsonObject) -> JsonObject {
+        let mut json = JsonObject::new()
+            .string("suite", &self.suite)
+            .number("packets_per_call", self.packets_per_call)
+            .number("bytes_per_packet", self.bytes_per_packet)
+            .number("duration", self.duration_secs)
+            .number("packets", self.packets)
+            .number("bytes", self.bytes)
+            .number("pps", self.pps())
+            .number("bytes_per_sec", self.bytes_per_sec())
+            .number("invalid_tx", self.invalid_tx)
+            .object("config", config);
+        if let Some(wire_bytes) = self.wire_bytes {
+            json = json.number("wire_bytes", wire_bytes)
+                .number("wire_bytes_per_sec", wire_bytes as f64 / self.duration_secs);
+        }
+        if let Some((packets, bytes)) = self.delivered {
+            json = json.number("delivered_packets", packets)
+                .number("delivered_bytes", bytes)
+                .number("delivered_pps", packets as f64 / self.duration_secs)
+                .number("delivered_bytes_per_sec", bytes as f64 / self.duration_secs);
+        }
+        if let Some(ref cpu) = self.cpu {
+            json = json.object("cpu", cpu.to_json(self.packets));
+        }
+        if let Some(ref nic) = self.nic {
+            json = json.object("nic", nic.to_json(self.packets));
+        }
+        if let Some(ref send_latency) = self.send_latency {
+            json = json.object("send_latency", send_latency.to_json());
+        }
+        match self.latency {
+            Some(ref latency) => json.object("latency", latency.to_json()),
+            None => json,
+        }
+    }
+}
+
+/// Mean, extremes and sample standard deviation of a set of measurements.
+#[derive(Debug, Clone, Copy)]
+pub struct Summary {
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+    pub stddev: f64,
+}
+
+impl Summary {
+    pub fn new(values: &[f64]) -> Self {
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let min = values.iter().cloned().fold(::std::f64::INFINITY, f64::min);
+        let max = values.iter().cloned().fold(::std::f64::NEG_INFINITY, f64::max);
+        let stddev = if values.len() > 1 {
+            let square_sum = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>();
+            (square_sum / (n - 1.0)).sqrt()
+        } else {
+            0.0
+        };
+        Summary {
+            mean: mean,
+            min: min,
+            max: max,
+            stddev: stddev,
+        }
+    }
+
+    pub fn to_json(&self) -> JsonObject {
+        JsonObject::new()
+            .number("mean", self.mean)
+            .number("min", self.min)
+            .number("max", self.max)
+            .number("stddev", self.stddev)
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
@@ -1,157 +1,397 @@
 use Config;
-use pnet::packet::ethernet::EtherTypes;
+use bench::{self, Benchmark, SendFn};
+use pnet::datalink::EthernetDataLinkReceiver;
+use pnet::packet::Packet;
+use pnet::packet::ethernet::{EtherType, EtherTypes, EthernetPacket};
 use pnet::packet::ip::IpNextHeaderProtocols;
-use progress;
-use rips::{self, EthernetChannel, NetworkStack};
+use pnet::packet::ipv4::Ipv4Packet;
+use progress::Printer;
+use rips::{self, EthernetChannel, NetworkStack, RxResult};
 use rips::{CustomPayload, Tx};
-use rips::ethernet::EthernetFields;
-use rips::ipv4::{Ipv4Fields, Ipv4Network};
+use rips::ethernet::{EthernetFields, EthernetListener, EthernetRx, MacAddr};
+use rips::ipv4::{IpListenerLookup, Ipv4Fields, Ipv4Listener, Ipv4Network, Ipv4Rx};
 use rips::udp::UdpSocket;
-use std::process;
+use rx::{self, RxCounter, RxThread};
+use sequence::Stream;
+use size_mix::Sampler;
+use wire::Encapsulation;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::SystemTime;
 
 lazy_static! {
     static ref DEFAULT_ROUTE: Ipv4Network = Ipv4Network::from_str("0.0.0.0/0").unwrap();
 }
 
-pub fn bench_ethernet(channel: EthernetChannel, config: &Config) {
-    let mut printer = progress::Printer::new();
-    let mut stack = create_stack(channel, config);
-    let interface = stack.interface(&config.iface).unwrap();
-    let mut tx = interface.ethernet_tx(config.dst_mac);
-
-    printer.print_title("Rips Ethernet sending");
-    let buffer = vec![0; 1000 * 1500];
-    let mut invalid_tx_count = 0;
-    for packets_per_call in vec![1, 10, 100, 1000] {
-        for bytes_per_packet in packet_sizes(config, Protocol::Ethernet) {
-            printer.print_line_description(&format!("Sending {}x{} bytes",
-                                                    packets_per_call,
-                                                    bytes_per_packet));
-            let mut pkgs = 0;
-            let mut bytes = 0;
-            let mut next_print_second = 1;
-            let timer = Instant::now();
-            loop {
-                let total_bytes = packets_per_call * bytes_per_packet;
-                let mut payload = CustomPayload::with_packet_size(EthernetFields(EtherTypes::Ipv4),
-                                                                  bytes_per_packet,
-                                                                  &buffer[..total_bytes]);
+pub struct EthernetBench {
+    stack: Arc<Mutex<NetworkStack>>,
+    dst_mac: MacAddr,
+}
+
+impl Benchmark for EthernetBench {
+    fn setup(channel: EthernetChannel,
+             config: &Config,
+             printer: &mut Printer)
+             -> io::Result<Self> {
+        let mut stack = create_stack(channel, config);
+        let dst_mac = if config.resolve_dst_mac {
+            resolve_dst_mac(&mut stack, config, printer)?
+        } else {
+            config.dst_mac
+        };
+        Ok(EthernetBench {
+            stack: Arc::new(Mutex::new(stack)),
+            dst_mac: dst_mac,
+        })
+    }
+
+    fn title(&self) -> &'static str {
+        "Rips Ethernet sending"
+    }
+
+    fn encapsulation(&self) -> Encapsulation {
+        Encapsulation::Ethernet
+    }
+
+    fn mixes_sizes(&self) -> bool {
+        true
+    }
+
+    fn batches(&self, config: &Config) -> Vec<usize> {
+        bench::batches(config, &[1, 10, 100, 1000])
+    }
+
+    fn sizes(&self, config: &Config, printer: &mut Printer) -> Vec<usize> {
+        packet_sizes(config, Protocol::Ethernet, printer)
+    }
+
+    /// Every sender gets its own Ethernet Tx, only locking the shared stack
+    /// when the Tx has to be recreated. With a size mix, every frame of a
+    /// batch draws its size. A Tx sends frames of one size per call, so the
+    /// batch is sent with one call per drawn size.
+    fn sender(&mut self, config: &Config, thread: usize) -> io::Result<Option<SendFn>> {
+        let stack = self.stack.clone();
+        let iface = config.iface.clone();
+        let dst_mac = self.dst_mac;
+        let mut sampler = config.size_mix.as_ref().map(|mix| mix.sampler(thread as u64));
+        let mut tx = stack.lock()
+            .unwrap()
+            .interface(&iface)
+            .map_err(|e| stack_error("Unable to find the interface", e))?
+            .ethernet_tx(dst_mac);
+        let mut buffer = Vec::new();
+        let mut groups = Vec::new();
+        Ok(Some(Box::new(move |packets_per_call: usize, bytes_per_packet: usize| {
+            groups.clear();
+            match sampler {
+                Some(ref mut sampler) => {
+                    for _ in 0..packets_per_call {
+                        let size = sampler.draw();
+                        match groups.iter().position(|&(group_size, _)| group_size == size) {
+                            Some(i) => groups[i].1 += 1,
+                            None => groups.push((size, 1)),
+                        }
+                    }
+                }
+                None => groups.push((bytes_per_packet, packets_per_call)),
+            }
+            let (mut sent_pkgs, mut sent_bytes) = (0, 0);
+            for &(size, pkgs) in &groups {
+                let total_bytes = pkgs * size;
+                if buffer.len() < total_bytes {
+                    buffer.resize(total_bytes, 0);
+                }
+                let mut payload =
+                    CustomPayload::with_packet_size(EthernetFields(EtherTypes::Ipv4),
+                                                    size,
+                                                    &buffer[..total_bytes]);
                 match tx.send(&mut payload) {
                     None => {
-                        invalid_tx_count += 1;
-                        tx = interface.ethernet_tx(config.dst_mac);
+                        tx = stack.lock()
+                            .unwrap()
+                            .interface(&iface)
+                            .map_err(|e| stack_error("Unable to find the interface", e))?
+                            .ethernet_tx(dst_mac);
+                        break;
                     }
-                    Some(Err(e)) => panic!("Unable to send: {:?}", e),
-                    _ => {
-                        pkgs += packets_per_call;
-                        bytes += total_bytes;
+                    Some(Err(e)) => return Err(e),
+                    Some(Ok(_)) => {
+                        sent_pkgs += pkgs;
+                        sent_bytes += total_bytes;
                     }
                 }
-
-                let elapsed = timer.elapsed();
-                if elapsed.as_secs() >= next_print_second {
-                    printer.print_statistics(pkgs, bytes, elapsed);
-                    next_print_second += 1;
-                }
-                if elapsed > config.duration {
-                    break;
-                }
             }
-            printer.end_line();
-        }
+            if sent_pkgs == 0 {
+                Ok(None)
+            } else {
+                Ok(Some((sent_pkgs, sent_bytes)))
+            }
+        })))
     }
-    println!("Benchmark resulted in {} InvalidTx", invalid_tx_count);
 }
 
-pub fn bench_ipv4(channel: EthernetChannel, config: &Config) {
-    let mut printer = progress::Printer::new();
-    let mut stack = create_stack(channel, config);
-    let mut tx = stack.ipv4_tx(*config.dst.ip()).unwrap();
+pub struct Ipv4Bench {
+    stack: Arc<Mutex<NetworkStack>>,
+    stream: Arc<Stream>,
+}
 
-    printer.print_title("Rips IPv4 sending");
+impl Benchmark for Ipv4Bench {
+    fn setup(channel: EthernetChannel,
+             config: &Config,
+             _printer: &mut Printer)
+             -> io::Result<Self> {
+        Ok(Ipv4Bench {
+            stack: Arc::new(Mutex::new(create_stack(channel, config))),
+            stream: Arc::new(Stream::new()),
+        })
+    }
+
+    fn title(&self) -> &'static str {
+        "Rips IPv4 sending"
+    }
+
+    fn encapsulation(&self) -> Encapsulation {
+        Encapsulation::Ipv4
+    }
+
+    fn mixes_sizes(&self) -> bool {
+        true
+    }
 
-    for bytes_per_packet in packet_sizes(config, Protocol::Ipv4) {
-        printer.print_line_description(&format!("Sending {} bytes per packet", bytes_per_packet));
-        let buffer = vec![0; bytes_per_packet];
+    fn sizes(&self, config: &Config, printer: &mut Printer) -> Vec<usize> {
+        packet_sizes(config, Protocol::Ipv4, printer)
+    }
 
-        let mut pkgs = 0;
-        let mut bytes = 0;
-        let timer = Instant::now();
-        let mut next_print = 1;
-        loop {
+    /// Every sender gets its own IPv4 Tx, only locking the shared stack when
+    /// the Tx has to be recreated.
+    fn sender(&mut self, config: &Config, thread: usize) -> io::Result<Option<SendFn>> {
+        let stack = self.stack.clone();
+        let stream = self.stream.clone();
+        let dst = *config.dst.ip();
+        let stamp = config.stamp;
+        let mut sampler = config.size_mix.as_ref().map(|mix| mix.sampler(thread as u64));
+        let mut tx = stack.lock()
+            .unwrap()
+            .ipv4_tx(dst)
+            .map_err(|e| stack_error("Unable to create an IPv4 Tx", e))?;
+        let mut buffer = Vec::new();
+        Ok(Some(Box::new(move |_packets_per_call: usize, bytes_per_packet: usize| {
+            let bytes_per_packet = payload_size(&mut sampler, &Protocol::Ipv4, bytes_per_packet);
+            buffer.resize(bytes_per_packet, 0);
+            if stamp {
+                let seq = stream.reserve(1);
+                stream.stamp(&mut buffer, seq, SystemTime::now());
+            }
             let mut payload = CustomPayload::new(Ipv4Fields(IpNextHeaderProtocols::Igmp),
                                                  &buffer[..]);
             match tx.send(&mut payload) {
                 None => {
-                    tx = stack.ipv4_tx(*config.dst.ip()).unwrap();
+                    tx = stack.lock()
+                        .unwrap()
+                        .ipv4_tx(dst)
+                        .map_err(|e| stack_error("Unable to create an IPv4 Tx", e))?;
+                    Ok(None)
                 }
-                Some(Err(e)) => {
-                    eprintln!("Error while sending to the network: {}", e);
-                    process::exit(1);
-                }
-                Some(Ok(_size)) => {
-                    pkgs += 1;
-                    bytes += bytes_per_packet;
-                }
-            }
-            let elapsed = timer.elapsed();
-            if elapsed.as_secs() >= next_print {
-                next_print += 1;
-                printer.print_statistics(pkgs, bytes, elapsed);
+                Some(Err(e)) => Err(e),
+                Some(Ok(_size)) => Ok(Some((1, bytes_per_packet))),
             }
-            if elapsed > config.duration {
-                break;
+        })))
+    }
+}
+
+pub struct UdpBench {
+    socket: UdpSocket,
+    stream: Arc<Stream>,
+}
+
+impl Benchmark for UdpBench {
+    fn setup(channel: EthernetChannel,
+             config: &Config,
+             _printer: &mut Printer)
+             -> io::Result<Self> {
+        let stack = Arc::new(Mutex::new(create_stack(channel, config)));
+        Ok(UdpBench {
+            socket: UdpSocket::bind(stack, config.src)?,
+            stream: Arc::new(Stream::new()),
+        })
+    }
+
+    fn title(&self) -> &'static str {
+        "Rips UDP sending"
+    }
+
+    fn encapsulation(&self) -> Encapsulation {
+        Encapsulation::Udp
+    }
+
+    fn mixes_sizes(&self) -> bool {
+        true
+    }
+
+    fn sizes(&self, config: &Config, printer: &mut Printer) -> Vec<usize> {
+        packet_sizes(config, Protocol::Udp, printer)
+    }
+
+    /// Every sender gets a clone of the socket. All clones share the stack,
+    /// and its lock, of the original socket.
+    fn sender(&mut self, config: &Config, thread: usize) -> io::Result<Option<SendFn>> {
+        let mut socket = self.socket.try_clone()?;
+        let stream = self.stream.clone();
+        let dst = config.dst;
+        let stamp = config.stamp;
+        let mut sampler = config.size_mix.as_ref().map(|mix| mix.sampler(thread as u64));
+        let mut buffer = Vec::new();
+        Ok(Some(Box::new(move |_packets_per_call: usize, bytes_per_packet: usize| {
+            let bytes_per_packet = payload_size(&mut sampler, &Protocol::Udp, bytes_per_packet);
+            buffer.resize(bytes_per_packet, 0);
+            if stamp {
+                let seq = stream.reserve(1);
+                stream.stamp(&mut buffer, seq, SystemTime::now());
             }
-        }
-        printer.end_line();
+            socket.send_to(&buffer, dst).map(|_size| Some((1, bytes_per_packet)))
+        })))
     }
 }
 
-pub fn bench_udp(channel: EthernetChannel, config: &Config) {
-    let mut printer = progress::Printer::new();
-    let stack = create_stack(channel, config);
+pub fn bench_ethernet_rx(channel: EthernetChannel,
+                         config: &Config,
+                         printer: &mut Printer)
+                         -> io::Result<()> {
+    let counter = RxCounter::new();
+    let listener = CountingListener { counter: counter.clone() };
+    let ethernet_rx = EthernetRx::new(vec![Box::new(listener)]);
 
-    let stack = Arc::new(Mutex::new(stack));
-    let mut socket = UdpSocket::bind(stack, config.src).unwrap();
+    printer.print_title("Rips Ethernet receiving");
+    printer.set_encapsulation(Encapsulation::Ethernet);
+    printer.start_case("Receiving IPv4 frames", 1, 0)?;
+    let rx_thread = spawn_rx(channel.receiver, ethernet_rx);
+    let measured = rx::measure(printer, &counter, config);
+    rx_thread.stop()?;
+    measured?;
+    printer.end_case();
+    Ok(())
+}
 
-    printer.print_title("Rips UDP sending");
+pub fn bench_ipv4_rx(channel: EthernetChannel,
+                     config: &Config,
+                     printer: &mut Printer)
+                     -> io::Result<()> {
+    let counter = RxCounter::new();
+    let mut protocol_listeners = HashMap::new();
+    for protocol in vec![IpNextHeaderProtocols::Igmp, IpNextHeaderProtocols::Udp] {
+        let listener = CountingListener { counter: counter.clone() };
+        protocol_listeners.insert(protocol, Box::new(listener) as Box<Ipv4Listener>);
+    }
+    let mut listeners: IpListenerLookup = HashMap::new();
+    listeners.insert(config.src_net.ip(), protocol_listeners);
+    let ipv4_rx = Ipv4Rx::new(Arc::new(Mutex::new(listeners)));
+    let ethernet_rx = EthernetRx::new(vec![Box::new(ipv4_rx)]);
+
+    printer.print_title("Rips IPv4 receiving");
+    printer.set_encapsulation(Encapsulation::Ipv4);
+    printer.start_case(&format!("Receiving packets to {}", config.src_net.ip()), 1, 0)?;
+    let rx_thread = spawn_rx(channel.receiver, ethernet_rx);
+    let measured = rx::measure(printer, &counter, config);
+    rx_thread.stop()?;
+    measured?;
+    printer.end_case();
+    Ok(())
+}
 
-    for bytes_per_packet in packet_sizes(config, Protocol::Udp) {
-        printer.print_line_description(&format!("Sending {} bytes per packet", bytes_per_packet));
-        let buffer = vec![0; bytes_per_packet];
+pub fn bench_udp_rx(channel: EthernetChannel,
+                    config: &Config,
+                    printer: &mut Printer)
+                    -> io::Result<()> {
+    let stack = create_stack(channel, config);
+
+    let stack = Arc::new(Mutex::new(stack));
+    let mut socket = UdpSocket::bind(stack, config.src)?;
 
-        let mut pkgs = 0;
-        let mut bytes = 0;
-        let timer = Instant::now();
-        let mut next_print = 1;
-        loop {
-            match socket.send_to(&buffer, config.dst) {
+    printer.print_title("Rips UDP receiving");
+    printer.set_encapsulation(Encapsulation::Udp);
+    printer.start_case(&format!("Receiving datagrams on {}", config.src), 1, 0)?;
+    let counter = RxCounter::new();
+    let thread_counter = counter.clone();
+    let running = Arc::new(AtomicBool::new(true));
+    let thread_running = running.clone();
+    let (error_tx, error_rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = vec![0; 65535];
+        while thread_running.load(Ordering::Relaxed) {
+            match socket.recv_from(&mut buffer) {
+                Ok((size, _src)) => thread_counter.add(size),
                 Err(e) => {
-                    eprintln!("Error while sending to the network: {}", e);
-                    process::exit(1);
+                    let _ = error_tx.send(e);
+                    return;
                 }
-                Ok(_size) => {
-                    pkgs += 1;
-                    bytes += bytes_per_packet;
-                }
-            }
-            let elapsed = timer.elapsed();
-            if elapsed.as_secs() >= next_print {
-                next_print += 1;
-                printer.print_statistics(pkgs, bytes, elapsed);
-            }
-            if elapsed > config.duration {
-                break;
             }
         }
-        printer.end_line();
+    });
+    let measured = rx::measure(printer, &counter, config);
+    // Rips sockets have no read timeout, so the thread can't be joined. It
+    // still counts the next datagram, after the case was reported, and then
+    // returns.
+    running.store(false, Ordering::Relaxed);
+    printer.print_message("The receiving thread and its stack keep consuming frames until the \
+                           next datagram arrives");
+    if let Ok(e) = error_rx.try_recv() {
+        return Err(bench::context(e, "Error while receiving from the network"));
+    }
+    measured?;
+    printer.end_case();
+    Ok(())
+}
+
+/// Listener counting every packet the rips receive path dispatches to it.
+struct CountingListener {
+    counter: RxCounter,
+}
+
+impl EthernetListener for CountingListener {
+    fn recv(&mut self, _time: SystemTime, packet: &EthernetPacket) -> RxResult {
+        self.counter.add(packet.payload().len());
+        Ok(())
+    }
+
+    fn get_ethertype(&self) -> EtherType {
+        EtherTypes::Ipv4
     }
 }
 
-fn create_stack(channel: EthernetChannel, config: &Config) -> NetworkStack {
+impl Ipv4Listener for CountingListener {
+    fn recv(&mut self, _time: SystemTime, packet: Ipv4Packet) -> RxResult {
+        self.counter.add(packet.payload().len());
+        Ok(())
+    }
+}
+
+/// Feeds every frame read from `receiver` through `ethernet_rx` on a
+/// separate thread, until stopped.
+fn spawn_rx(mut receiver: Box<EthernetDataLinkReceiver>,
+            mut ethernet_rx: EthernetRx)
+            -> RxThread {
+    RxThread::spawn(move |running| {
+        let mut rx_iter = receiver.iter();
+        while running.load(Ordering::Relaxed) {
+            match rx_iter.next() {
+                Ok(packet) => {
+                    let _ = ethernet_rx.recv(SystemTime::now(), &packet);
+                }
+                Err(ref e) if rx::is_timeout(e) => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    })
+}
+
+pub fn create_stack(channel: EthernetChannel, config: &Config) -> NetworkStack {
     let mut stack = rips::NetworkStack::new();
     stack.add_interface(config.iface.clone(), channel).unwrap();
     stack.add_ipv4(&config.iface, config.src_net).unwrap();
@@ -162,33 +402,102 @@ fn create_stack(channel: EthernetChannel, config: &Config) -> NetworkStack {
     stack
 }
 
+/// Wraps an error of the stack, which only implements `Debug`, in an
+/// `io::Error` prefixed with `what`.
+fn stack_error<E: fmt::Debug>(what: &str, e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("{}: {:?}", what, e))
+}
+
+/// Resolves the MAC of the next hop towards `config.dst` through the ARP
+/// machinery of `stack`.
+fn resolve_dst_mac(stack: &mut NetworkStack,
+                   config: &Config,
+                   printer: &mut Printer)
+                   -> io::Result<MacAddr> {
+    let dst = *config.dst.ip();
+    let next_hop = if config.src_net.contains(dst) { dst } else { config.gw };
+    // Creating an IPv4 Tx makes the stack resolve the next hop with ARP.
+    if let Err(e) = stack.ipv4_tx(dst) {
+        let msg = format!("Unable to resolve the MAC of {}: {:?}", next_hop, e);
+        return Err(io::Error::new(io::ErrorKind::Other, msg));
+    }
+    let interface = match stack.interface(&config.iface) {
+        Ok(interface) => interface,
+        Err(e) => return Err(stack_error("Unable to find the interface", e)),
+    };
+    match interface.arp_table().get(next_hop) {
+        Ok(mac) => {
+            printer.print_message(&format!("Resolved {} to {}", next_hop, mac));
+            Ok(mac)
+        }
+        Err(_) => {
+            let msg = format!("Unable to resolve the MAC of {}", next_hop);
+            Err(io::Error::new(io::ErrorKind::Other, msg))
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Debug)]
-enum Protocol {
+pub enum Protocol {
     Ethernet,
     Ipv4,
     Udp,
+    Icmp,
 }
 
-fn packet_sizes(config: &Config, protocol: Protocol) -> Vec<usize> {
+pub fn packet_sizes(config: &Config, protocol: Protocol, printer: &mut Printer) -> Vec<usize> {
     let mut sizes = Vec::new();
     if config.size_min {
         sizes.push(match protocol {
             Protocol::Ethernet => 20 + 8,
             Protocol::Ipv4 => 8,
             Protocol::Udp => 0,
+            Protocol::Icmp => 8,
         });
     }
+    let headers = headers(&protocol);
     if config.size_mtu {
-        let size = config.mtu -
-                   match protocol {
-            Protocol::Ethernet => 0,
-            Protocol::Ipv4 => 20,
-            Protocol::Udp => 20 + 8,
-        };
-        sizes.push(size);
+        sizes.push(config.mtu - headers);
     }
-    if config.size_max && protocol != Protocol::Ethernet {
-        sizes.push(65000);
+    if config.size_max {
+        if protocol == Protocol::Ethernet {
+            printer.print_message("Skipping the max size, Ethernet frames can't exceed the MTU");
+        } else {
+            sizes.push(65000);
+        }
+    }
+    // Sizes too small to hold the headers are left out.
+    for &size in &config.sizes {
+        if size >= headers {
+            sizes.push(size - headers);
+        } else {
+            let text = format!("Skipping size {}, smaller than the {} bytes of headers",
+                               size,
+                               headers);
+            printer.print_message(&text);
+        }
     }
     sizes
 }
+
+/// Headers between the payload of `protocol` and the IP packet carrying it.
+fn headers(protocol: &Protocol) -> usize {
+    match *protocol {
+        Protocol::Ethernet => 0,
+        Protocol::Ipv4 => 20,
+        Protocol::Udp => 20 + 8,
+        Protocol::Icmp => 20 + 8,
+    }
+}
+
+/// The payload to send next. Drawn from the size mix if there is one,
+/// otherwise `bytes_per_packet`.
+fn payload_size(sampler: &mut Option<Sampler>,
+                protocol: &Protocol,
+                bytes_per_packet: usize)
+                -> usize {
+    match *sampler {
+        Some(ref mut sampler) => sampler.draw().saturating_sub(headers(protocol)),
+        None => bytes_per_packet,
+    }
+}
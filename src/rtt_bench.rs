@@ -0,0 +1,209 @@
+use Config;
+use bench;
+use latency::Latency;
+use pnet::packet::Packet;
+use pnet::packet::icmp::{IcmpPacket, IcmpTypes};
+use pnet::packet::ipv4::Ipv4Packet;
+use progress::{Meter, Printer};
+use rips::{EthernetChannel, RxResult};
+use rips::icmp::IcmpListener;
+use rips::udp::UdpSocket;
+use rips_bench::{self, Protocol};
+use sequence::{read_seq, write_seq};
+use std::cmp;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long to wait for the reply to a request before counting it as lost.
+const REPLY_TIMEOUT_MS: u64 = 1000;
+
+pub fn bench_icmp(channel: EthernetChannel,
+                  config: &Config,
+                  printer: &mut Printer)
+                  -> io::Result<()> {
+    let mut stack = rips_bench::create_stack(channel, config);
+    let (reply_tx, reply_rx) = mpsc::channel();
+    let listener = EchoReplyListener { replies: reply_tx };
+    stack.icmp_listen(config.src_net.ip(), IcmpTypes::EchoReply, listener).unwrap();
+    let dst = *config.dst.ip();
+    let mut tx = stack.icmp_tx(dst).unwrap();
+
+    printer.print_title("Rips ICMP echo round trip");
+
+    let mut seq = 0;
+    for bytes_per_packet in rips_bench::packet_sizes(config, Protocol::Icmp, printer) {
+        // Room is needed for the sequence number identifying the reply.
+        let bytes_per_packet = cmp::max(bytes_per_packet, 8);
+        printer.start_case(&format!("Pinging with {} bytes of payload", bytes_per_packet),
+                           1,
+                           bytes_per_packet)?;
+        measure_rtt(printer, config, bytes_per_packet, &reply_rx, &mut seq, |payload| {
+            match tx.send_echo(payload) {
+                None => {
+                    tx = stack.icmp_tx(dst).unwrap();
+                    Ok(false)
+                }
+                Some(Err(e)) => Err(e),
+                Some(Ok(_)) => Ok(true),
+            }
+        })?;
+        printer.end_case();
+    }
+    Ok(())
+}
+
+pub fn bench_udp_rtt(channel: EthernetChannel,
+                     config: &Config,
+                     printer: &mut Printer)
+                     -> io::Result<()> {
+    let stack = rips_bench::create_stack(channel, config);
+
+    let stack = Arc::new(Mutex::new(stack));
+    let mut socket = UdpSocket::bind(stack, config.src)?;
+    let mut rx_socket = socket.try_clone()?;
+    let (reply_tx, reply_rx) = mpsc::channel();
+    let running = Arc::new(AtomicBool::new(true));
+    let thread_running = running.clone();
+    thread::spawn(move || {
+        let mut buffer = vec![0; 65535];
+        while thread_running.load(Ordering::Relaxed) {
+            match rx_socket.recv_from(&mut buffer) {
+                Ok((size, _src)) => {
+                    let received_at = Instant::now();
+                    if let Some(seq) = read_seq(&buffer[..size]) {
+                        if reply_tx.send(Ok((seq, received_at))).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(e) => {
+                    let _ = reply_tx.send(Err(e));
+                    return;
+                }
+            }
+        }
+    });
+
+    printer.print_title("Rips UDP round trip");
+    let measured = measure_udp_sizes(printer, config, &mut socket, &reply_rx);
+    // Rips sockets have no read timeout, so the reply thread can't be joined.
+    // It exits once the next datagram arrives.
+    running.store(false, Ordering::Relaxed);
+    printer.print_message("The reply thread and its stack keep receiving until the next \
+                           datagram arrives");
+    measured
+}
+
+/// Runs one round trip case per packet size, sending from `socket`.
+fn measure_udp_sizes(printer: &mut Printer,
+                     config: &Config,
+                     socket: &mut UdpSocket,
+                     replies: &Receiver<io::Result<(u64, Instant)>>)
+                     -> io::Result<()> {
+    let mut seq = 0;
+    for bytes_per_packet in rips_bench::packet_sizes(config, Protocol::Udp, printer) {
+        // Room is needed for the sequence number identifying the reply.
+        let bytes_per_packet = cmp::max(bytes_per_packet, 8);
+        printer.start_case(&format!("Echoing {} bytes per packet", bytes_per_packet),
+                           1,
+                           bytes_per_packet)?;
+        measure_rtt(printer, config, bytes_per_packet, replies, &mut seq, |payload| {
+            socket.send_to(payload, config.dst).map(|_size| true)
+        })?;
+        printer.end_case();
+    }
+    Ok(())
+}
+
+/// Runs all repetitions of one round trip case. `send` sends a request with
+/// the given payload and returns false if it had to be skipped.
+fn measure_rtt<F>(printer: &mut Printer,
+                  config: &Config,
+                  bytes_per_packet: usize,
+                  replies: &Receiver<io::Result<(u64, Instant)>>,
+                  seq: &mut u64,
+                  mut send: F)
+                  -> io::Result<()>
+    where F: FnMut(&[u8]) -> io::Result<bool>
+{
+    let mut payload = vec![0; bytes_per_packet];
+    for _ in 0..config.repeat {
+        let mut meter = Meter::new(config);
+        let mut latency = Latency::new();
+        while meter.tick(printer)? {
+            *seq += 1;
+            write_seq(&mut payload, *seq);
+            let sent_at = Instant::now();
+            let sent = send(&payload)
+                .map_err(|e| bench::context(e, "Error while sending to the network"))?;
+            if !sent {
+                continue;
+            }
+            let reply = wait_for_reply(replies, *seq, sent_at)
+                .map_err(|e| bench::context(e, "Error while receiving from the network"))?;
+            if meter.warming_up() {
+                continue;
+            }
+            match reply {
+                Some(rtt) => {
+                    latency.add(rtt);
+                    meter.add(1, bytes_per_packet);
+                }
+                None => latency.add_lost(),
+            }
+        }
+        printer.set_latency(latency);
+        printer.end_repetition();
+    }
+    Ok(())
+}
+
+/// Waits for the reply carrying `seq`, discarding late replies to earlier
+/// requests. Returns the round-trip time, or `None` if the request timed out.
+/// Fails if receiving the replies did.
+fn wait_for_reply(replies: &Receiver<io::Result<(u64, Instant)>>,
+                  seq: u64,
+                  sent_at: Instant)
+                  -> io::Result<Option<Duration>> {
+    let timeout = Duration::from_millis(REPLY_TIMEOUT_MS);
+    loop {
+        let elapsed = sent_at.elapsed();
+        if elapsed >= timeout {
+            return Ok(None);
+        }
+        match replies.recv_timeout(timeout - elapsed) {
+            Ok(Ok((reply_seq, received_at))) if reply_seq == seq => {
+                return Ok(Some(received_at.duration_since(sent_at)))
+            }
+            Ok(Ok(_)) => continue,
+            Ok(Err(e)) => return Err(e),
+            Err(_) => return Ok(None),
+        }
+    }
+}
+
+/// Forwards the sequence number of every echo reply, together with the time
+/// it arrived, to the sending thread.
+struct EchoReplyListener {
+    replies: Sender<io::Result<(u64, Instant)>>,
+}
+
+impl IcmpListener for EchoReplyListener {
+    fn recv(&mut self, _time: SystemTime, packet: &Ipv4Packet) -> RxResult {
+        let received_at = Instant::now();
+        if let Some(icmp_pkg) = IcmpPacket::new(packet.payload()) {
+            // The echo identifier and sequence number precede the data.
+            let icmp_payload = icmp_pkg.payload();
+            if icmp_payload.len() >= 4 {
+                if let Some(seq) = read_seq(&icmp_payload[4..]) {
+                    let _ = self.replies.send(Ok((seq, received_at)));
+                }
+            }
+        }
+        Ok(())
+    }
+}
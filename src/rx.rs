@@ -0,0 +1,102 @@
+use Config;
+use bench;
+use progress::{Meter, Printer};
+
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How often counters updated by other threads are sampled.
+pub const SAMPLE_INTERVAL_MS: u64 = 10;
+
+/// Timeout of the reads of receive threads, so they notice when they are
+/// stopped even if nothing arrives.
+pub const READ_TIMEOUT_MS: u64 = 100;
+
+/// A thread receiving packets for a case, stopped when the case ends so it
+/// doesn't compete with the following suites.
+pub struct RxThread {
+    running: Arc<AtomicBool>,
+    handle: JoinHandle<io::Result<()>>,
+}
+
+impl RxThread {
+    /// Spawns `receive` on a new thread. It should return soon after the
+    /// flag it's given is cleared, or when receiving fails.
+    pub fn spawn<F>(receive: F) -> Self
+        where F: FnOnce(&AtomicBool) -> io::Result<()> + Send + 'static
+    {
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        RxThread {
+            running: running,
+            handle: thread::spawn(move || receive(&thread_running)),
+        }
+    }
+
+    /// Clears the flag of the thread and waits for it to return. Returns
+    /// the error receiving failed with, if it did.
+    pub fn stop(self) -> io::Result<()> {
+        self.running.store(false, Ordering::Relaxed);
+        self.handle.join().unwrap().map_err(|e| {
+            bench::context(e, "Error while receiving from the network")
+        })
+    }
+}
+
+/// Whether `e` is a read timing out rather than failing.
+pub fn is_timeout(e: &io::Error) -> bool {
+    e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut
+}
+
+/// Packet and byte counters shared between a receiving or sending thread and
+/// the thread reporting the statistics.
+#[derive(Clone)]
+pub struct RxCounter {
+    pkgs: Arc<AtomicUsize>,
+    bytes: Arc<AtomicUsize>,
+}
+
+impl RxCounter {
+    pub fn new() -> Self {
+        RxCounter {
+            pkgs: Arc::new(AtomicUsize::new(0)),
+            bytes: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn add(&self, bytes: usize) {
+        self.pkgs.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn add_packets(&self, pkgs: usize, bytes: usize) {
+        self.pkgs.fetch_add(pkgs, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> (usize, usize) {
+        (self.pkgs.load(Ordering::Relaxed), self.bytes.load(Ordering::Relaxed))
+    }
+}
+
+/// Samples `counter` and prints the receive rate for every repetition of
+/// the case.
+pub fn measure(printer: &mut Printer, counter: &RxCounter, config: &Config) -> io::Result<()> {
+    printer.set_receiving();
+    for _ in 0..config.repeat {
+        let mut meter = Meter::new(config);
+        let (mut last_pkgs, mut last_bytes) = counter.get();
+        while meter.tick(printer)? {
+            thread::sleep(Duration::from_millis(SAMPLE_INTERVAL_MS));
+            let (pkgs, bytes) = counter.get();
+            meter.add(pkgs - last_pkgs, bytes - last_bytes);
+            last_pkgs = pkgs;
+            last_bytes = bytes;
+        }
+        printer.end_repetition();
+    }
+    Ok(())
+}
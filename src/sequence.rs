@@ -0,0 +1,323 @@
+use progress::duration_secs;
+use results::JsonObject;
+
+use std::collections::BTreeSet;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Number of payload bytes needed for a stamp: a magic number, the stream id,
+/// the sequence number and the send timestamp.
+pub const STAMP_SIZE: usize = 4 + 4 + 8 + 8 + 4;
+
+/// Starts every stamp, so payloads that are not stamped are told apart.
+const STAMP_MAGIC: u64 = 0x52425351;
+
+/// Gaps in the sequence larger than this are counted as lost without
+/// remembering every missing sequence number. Missing sequence numbers
+/// further than this behind the next expected one are given up on too.
+const MAX_TRACKED_GAP: u64 = 1 << 16;
+
+/// Streams created by this process, to tell apart streams created at the
+/// same time.
+static STREAMS: AtomicUsize = AtomicUsize::new(0);
+
+/// Writes `seq` as big endian into the first eight bytes of `payload`, if
+/// there is room.
+pub fn write_seq(payload: &mut [u8], seq: u64) {
+    if payload.len() >= 8 {
+        write_u64(&mut payload[..8], seq);
+    }
+}
+
+pub fn read_seq(payload: &[u8]) -> Option<u64> {
+    if payload.len() >= 8 {
+        Some(read_u64(&payload[..8]))
+    } else {
+        None
+    }
+}
+
+/// A stream of stamped packets, shared by all threads sending it. Sequence
+/// numbers start at one in every stream.
+pub struct Stream {
+    id: u32,
+    seq: AtomicUsize,
+}
+
+impl Stream {
+    /// Creates a stream with an id that is very likely different from the
+    /// ids of earlier streams, also of other processes.
+    pub fn new() -> Self {
+        let since_epoch =
+            SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::new(0, 0));
+        let count = STREAMS.fetch_add(1, Ordering::Relaxed) as u64;
+        let seed = since_epoch.as_secs() ^ ((since_epoch.subsec_nanos() as u64) << 20) ^ count;
+        Stream {
+            id: (seed.wrapping_mul(0x9e3779b97f4a7c15) >> 32) as u32,
+            seq: AtomicUsize::new(0),
+        }
+    }
+
+    /// Reserves `count` consecutive sequence numbers and returns the first.
+    pub fn reserve(&self, count: usize) -> u64 {
+        self.seq.fetch_add(count, Ordering::Relaxed) as u64 + 1
+    }
+
+    /// Stamps `payload` with the stream id, `seq` and the time it is sent.
+    /// Payloads shorter than `STAMP_SIZE` are left untouched.
+    pub fn stamp(&self, payload: &mut [u8], seq: u64, time: SystemTime) {
+        if payload.len() < STAMP_SIZE {
+            return;
+        }
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::new(0, 0));
+        write_u64(&mut payload[..4], STAMP_MAGIC);
+        write_u64(&mut payload[4..8], self.id as u64);
+        write_u64(&mut payload[8..16], seq);
+        write_u64(&mut payload[16..24], since_epoch.as_secs());
+        write_u64(&mut payload[24..28], since_epoch.subsec_nanos() as u64);
+    }
+}
+
+/// Reads the stream id, sequence number and send time written by
+/// `Stream::stamp`. Returns `None` if `payload` is not stamped.
+pub fn read_stamp(payload: &[u8]) -> Option<(u32, u64, SystemTime)> {
+    if payload.len() < STAMP_SIZE || read_u64(&payload[..4]) != STAMP_MAGIC {
+        return None;
+    }
+    let stream = read_u64(&payload[4..8]) as u32;
+    let seq = read_u64(&payload[8..16]);
+    let secs = read_u64(&payload[16..24]);
+    let nanos = read_u64(&payload[24..28]);
+    if nanos >= 1_000_000_000 {
+        return None;
+    }
+    UNIX_EPOCH.checked_add(Duration::new(secs, nanos as u32)).map(|sent| (stream, seq, sent))
+}
+
+/// Writes the `buffer.len()` least significant bytes of `value` as big endian.
+fn write_u64(buffer: &mut [u8], value: u64) {
+    let len = buffer.len();
+    for (i, byte) in buffer.iter_mut().enumerate() {
+        *byte = (value >> (8 * (len - 1 - i))) as u8;
+    }
+}
+
+fn read_u64(buffer: &[u8]) -> u64 {
+    buffer.iter().fold(0, |value, &byte| (value << 8) | byte as u64)
+}
+
+/// Keeps track of lost, duplicated and reordered packets and the one-way
+/// delay of a stream of stamped packets. Starts over when packets of another
+/// stream arrive. A packet arriving after its sequence number was given up
+/// on is counted as a duplicate.
+pub struct SequenceTracker {
+    stream: Option<u32>,
+    next_seq: Option<u64>,
+    missing: BTreeSet<u64>,
+    /// Lost packets in gaps too large to track, or given up on.
+    untracked_lost: usize,
+    received: usize,
+    duplicates: usize,
+    reordered: usize,
+    delay_sum: f64,
+    delay_min: f64,
+    delay_max: f64,
+    last_transit: Option<f64>,
+    jitter: f64,
+}
+
+impl SequenceTracker {
+    pub fn new() -> Self {
+        SequenceTracker {
+            stream: None,
+            next_seq: None,
+            missing: BTreeSet::new(),
+            untracked_lost: 0,
+            received: 0,
+            duplicates: 0,
+            reordered: 0,
+            delay_sum: 0.0,
+            delay_min: ::std::f64::INFINITY,
+            delay_max: ::std::f64::NEG_INFINITY,
+            last_transit: None,
+            jitter: 0.0,
+        }
+    }
+
+    pub fn add(&mut self, stream: u32, seq: u64, sent: SystemTime, received: SystemTime) {
+        if self.stream != Some(stream) {
+            *self = SequenceTracker::new();
+            self.stream = Some(stream);
+        }
+        let next_seq = self.next_seq.unwrap_or(seq);
+        if seq >= next_seq {
+            if seq - next_seq > MAX_TRACKED_GAP {
+                self.untracked_lost += (seq - next_seq) as usize;
+            } else {
+                for missing_seq in next_seq..seq {
+                    self.missing.insert(missing_seq);
+                }
+            }
+            self.next_seq = Some(seq + 1);
+            self.evict_missing(seq + 1);
+        } else if self.missing.remove(&seq) {
+            self.reordered += 1;
+        } else {
+            self.duplicates += 1;
+            return;
+        }
+        self.received += 1;
+
+        // The delay is only meaningful if the clocks of both ends are synced.
+        let transit = match received.duration_since(sent) {
+            Ok(delay) => duration_secs(delay),
+            Err(e) => -duration_secs(e.duration()),
+        };
+        self.delay_sum += transit;
+        self.delay_min = self.delay_min.min(transit);
+        self.delay_max = self.delay_max.max(transit);
+        // Interarrival jitter as defined in RFC 3550.
+        if let Some(last_transit) = self.last_transit {
+            self.jitter += ((transit - last_transit).abs() - self.jitter) / 16.0;
+        }
+        self.last_transit = Some(transit);
+    }
+
+    /// Moves the missing sequence numbers too far behind `next_seq` to the
+    /// untracked lost ones, so loss spread over a long run stays bounded.
+    fn evict_missing(&mut self, next_seq: u64) {
+        if next_seq <= MAX_TRACKED_GAP {
+            return;
+        }
+        let horizon = next_seq - MAX_TRACKED_GAP;
+        if self.missing.iter().next().map_or(false, |&oldest| oldest < horizon) {
+            let kept = self.missing.split_off(&horizon);
+            self.untracked_lost += mem::replace(&mut self.missing, kept).len();
+        }
+    }
+
+    pub fn lost(&self) -> usize {
+        self.missing.len() + self.untracked_lost
+    }
+
+    pub fn mean_delay(&self) -> f64 {
+        if self.received > 0 {
+            self.delay_sum / self.received as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// Short human readable summary, to show next to the receive rate.
+    pub fn status(&self) -> String {
+        format!("{} lost, {} duplicated, {} reordered, {:.1} µs delay, {:.1} µs jitter",
+                self.lost(),
+                self.duplicates,
+                self.reordered,
+                self.mean_delay() * 1_000_000f64,
+                self.jitter * 1_000_000f64)
+    }
+
+    pub fn to_json(&self) -> JsonObject {
+        let json = JsonObject::new()
+            .number("received", self.received)
+            .number("lost", self.lost())
+            .number("duplicated", self.duplicates)
+            .number("reordered", self.reordered)
+            .number("jitter", self.jitter);
+        if self.received > 0 {
+            json.number("delay_mean", self.mean_delay())
+                .number("delay_min", self.delay_min)
+                .number("delay_max", self.delay_max)
+        } else {
+            json
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn add(tracker: &mut SequenceTracker, stream: u32, seq: u64) {
+        let sent = UNIX_EPOCH + Duration::from_millis(seq);
+        tracker.add(stream, seq, sent, sent + Duration::from_millis(1));
+    }
+
+    #[test]
+    fn counts_lost() {
+        let mut tracker = SequenceTracker::new();
+        for &seq in &[1, 2, 5, 6, 8] {
+            add(&mut tracker, 1, seq);
+        }
+        assert_eq!(tracker.lost(), 3);
+        assert_eq!(tracker.duplicates, 0);
+        assert_eq!(tracker.reordered, 0);
+        assert_eq!(tracker.received, 5);
+    }
+
+    #[test]
+    fn counts_reordered() {
+        let mut tracker = SequenceTracker::new();
+        for &seq in &[1, 3, 2, 4] {
+            add(&mut tracker, 1, seq);
+        }
+        assert_eq!(tracker.lost(), 0);
+        assert_eq!(tracker.reordered, 1);
+        assert_eq!(tracker.received, 4);
+    }
+
+    #[test]
+    fn counts_duplicates() {
+        let mut tracker = SequenceTracker::new();
+        for &seq in &[1, 2, 2, 3, 1] {
+            add(&mut tracker, 1, seq);
+        }
+        assert_eq!(tracker.lost(), 0);
+        assert_eq!(tracker.duplicates, 2);
+        assert_eq!(tracker.received, 3);
+    }
+
+    #[test]
+    fn starts_over_on_another_stream() {
+        let mut tracker = SequenceTracker::new();
+        for &seq in &[1, 3, 3] {
+            add(&mut tracker, 1, seq);
+        }
+        for &seq in &[10, 11] {
+            add(&mut tracker, 2, seq);
+        }
+        assert_eq!(tracker.lost(), 0);
+        assert_eq!(tracker.duplicates, 0);
+        assert_eq!(tracker.received, 2);
+    }
+
+    #[test]
+    fn counts_large_gaps_untracked() {
+        let mut tracker = SequenceTracker::new();
+        add(&mut tracker, 1, 1);
+        add(&mut tracker, 1, MAX_TRACKED_GAP + 3);
+        assert_eq!(tracker.lost(), MAX_TRACKED_GAP as usize + 1);
+        assert!(tracker.missing.is_empty());
+    }
+
+    #[test]
+    fn gives_up_on_old_missing() {
+        let mut tracker = SequenceTracker::new();
+        add(&mut tracker, 1, 1);
+        add(&mut tracker, 1, 3);
+        for seq in 4..MAX_TRACKED_GAP + 10 {
+            add(&mut tracker, 1, seq);
+        }
+        assert_eq!(tracker.lost(), 1);
+        assert!(tracker.missing.is_empty());
+        assert_eq!(tracker.untracked_lost, 1);
+        // Too late to be told apart from a duplicate.
+        add(&mut tracker, 1, 2);
+        assert_eq!(tracker.duplicates, 1);
+        assert_eq!(tracker.lost(), 1);
+    }
+}
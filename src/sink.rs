@@ -0,0 +1,205 @@
+use Config;
+use args::SinkReceiver;
+use bench;
+use control::{self, Event};
+use pnet::packet::Packet;
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket};
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::udp::UdpPacket;
+use progress::{Printer, duration_secs};
+use rips::EthernetChannel;
+use rips::udp::UdpSocket;
+use rips_bench;
+use rx::RxCounter;
+use sequence::{self, SequenceTracker};
+use wire::Encapsulation;
+use std::io;
+use std::net::SocketAddrV4;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Receives datagrams sent to `config.src` and reports the delivered rate
+/// and the loss, duplication, reordering and delay of stamped datagrams. Runs
+/// for `duration`, or until the process is killed if it's `None`. When
+/// controlled by a sender, it stops when the sender disconnects. Fails if
+/// receiving does.
+pub fn run(channel: EthernetChannel,
+           config: &Config,
+           receiver: SinkReceiver,
+           duration: Option<Duration>,
+           printer: &mut Printer)
+           -> io::Result<()> {
+    let counter = RxCounter::new();
+    let tracker = Arc::new(Mutex::new(SequenceTracker::new()));
+    let (event_tx, event_rx) = mpsc::channel();
+    let (error_tx, error_rx) = mpsc::channel();
+    if let Some(control_addr) = config.control {
+        if let Err(e) = control::spawn_server(control_addr, counter.clone(), event_tx.clone()) {
+            let what = format!("Unable to listen for control connections on {}", control_addr);
+            return Err(bench::context(e, &what));
+        }
+    }
+    match receiver {
+        SinkReceiver::Rips => {
+            printer.print_title("Rips UDP sink");
+            printer.set_encapsulation(Encapsulation::Udp);
+            spawn_rips_rx(channel, config, counter.clone(), tracker.clone(), error_tx)?;
+        }
+        SinkReceiver::Pnet => {
+            printer.print_title("Raw pnet datalink sink");
+            printer.set_encapsulation(Encapsulation::Udp);
+            spawn_pnet_rx(channel, config, counter.clone(), tracker.clone(), error_tx);
+        }
+    }
+    printer.start_case(&format!("Receiving datagrams on {}", config.src), 1, 0)?;
+
+    let timer = Instant::now();
+    let mut next_print_second = 1;
+    let mut current_case = String::new();
+    loop {
+        let next_print = Duration::new(next_print_second, 0);
+        let elapsed = timer.elapsed();
+        if elapsed < next_print {
+            match event_rx.recv_timeout(next_print - elapsed) {
+                Ok(Event::Case(case)) => {
+                    // Every case sends a stream of its own.
+                    *tracker.lock().unwrap() = SequenceTracker::new();
+                    current_case = case;
+                    continue;
+                }
+                Ok(Event::Result { packets, bytes, duration }) => {
+                    let secs = duration_secs(duration);
+                    printer.print_message(&format!("Case {}: {} packets, {} bytes delivered in \
+                                                    {:.2} s, {:.0} pps - {}",
+                                                   current_case,
+                                                   packets,
+                                                   bytes,
+                                                   secs,
+                                                   packets as f64 / secs,
+                                                   tracker.lock().unwrap().status()));
+                    continue;
+                }
+                Ok(Event::Closed) => break,
+                Err(_) => (),
+            }
+        }
+        next_print_second += 1;
+        if let Ok(e) = error_rx.try_recv() {
+            return Err(bench::context(e, "Error while receiving from the network"));
+        }
+
+        let elapsed = timer.elapsed();
+        let (pkgs, bytes) = counter.get();
+        printer.set_status(&tracker.lock().unwrap().status());
+        printer.print_statistics(pkgs, bytes, elapsed);
+        if duration.map(|duration| elapsed > duration).unwrap_or(false) {
+            break;
+        }
+    }
+    printer.add_case_details("sequence", tracker.lock().unwrap().to_json());
+    printer.end_case();
+    Ok(())
+}
+
+/// Receives through a rips `UdpSocket`, so only datagrams that made it
+/// through the whole stack are counted. A receive error is sent on `errors`
+/// and ends the thread.
+fn spawn_rips_rx(channel: EthernetChannel,
+                 config: &Config,
+                 counter: RxCounter,
+                 tracker: Arc<Mutex<SequenceTracker>>,
+                 errors: Sender<io::Error>)
+                 -> io::Result<()> {
+    let stack = rips_bench::create_stack(channel, config);
+
+    let stack = Arc::new(Mutex::new(stack));
+    let mut socket = UdpSocket::bind(stack, config.src)?;
+    thread::spawn(move || {
+        let mut buffer = vec![0; 65535];
+        loop {
+            match socket.recv_from(&mut buffer) {
+                Ok((size, _src)) => {
+                    let received_at = SystemTime::now();
+                    counter.add(size);
+                    if let Some((stream, seq, sent_at)) = sequence::read_stamp(&buffer[..size]) {
+                        tracker.lock().unwrap().add(stream, seq, sent_at, received_at);
+                    }
+                }
+                Err(e) => {
+                    let _ = errors.send(e);
+                    return;
+                }
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Receives raw frames and counts the ones belonging to the benchmark flow,
+/// UDP to `config.src` or the IGMP packets of the rips IPv4 suite to its
+/// address. Does not reassemble fragmented packets. A receive error is sent
+/// on `errors` and ends the thread.
+fn spawn_pnet_rx(channel: EthernetChannel,
+                 config: &Config,
+                 counter: RxCounter,
+                 tracker: Arc<Mutex<SequenceTracker>>,
+                 errors: Sender<io::Error>) {
+    let mut receiver = channel.receiver;
+    let local = config.src;
+    thread::spawn(move || {
+        let mut rx_iter = receiver.iter();
+        loop {
+            match rx_iter.next() {
+                Ok(eth_pkg) => {
+                    let received_at = SystemTime::now();
+                    if let Some(payload) = flow_payload(&eth_pkg, local) {
+                        counter.add(payload.len());
+                        if let Some((stream, seq, sent_at)) = sequence::read_stamp(payload) {
+                            tracker.lock().unwrap().add(stream, seq, sent_at, received_at);
+                        }
+                    }
+                }
+                Err(e) => {
+                    let _ = errors.send(e);
+                    return;
+                }
+            }
+        }
+    });
+}
+
+/// Returns the payload of `eth_pkg` if it belongs to the benchmark flow.
+fn flow_payload<'p>(eth_pkg: &'p EthernetPacket, local: SocketAddrV4) -> Option<&'p [u8]> {
+    if eth_pkg.get_ethertype() != EtherTypes::Ipv4 {
+        return None;
+    }
+    let ip_pkg = match Ipv4Packet::new(eth_pkg.payload()) {
+        Some(ip_pkg) => ip_pkg,
+        None => return None,
+    };
+    if ip_pkg.get_destination() != *local.ip() {
+        return None;
+    }
+    let header_len = ip_pkg.get_header_length() as usize * 4;
+    let total_len = ip_pkg.get_total_length() as usize;
+    let ip_data = eth_pkg.payload();
+    if header_len < 20 || total_len < header_len || total_len > ip_data.len() {
+        return None;
+    }
+    let ip_payload = &ip_data[header_len..total_len];
+    match ip_pkg.get_next_level_protocol() {
+        IpNextHeaderProtocols::Igmp => Some(ip_payload),
+        IpNextHeaderProtocols::Udp => {
+            match UdpPacket::new(ip_payload) {
+                Some(ref udp_pkg) if udp_pkg.get_destination() == local.port() => {
+                    Some(&ip_payload[8..])
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
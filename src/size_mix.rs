@@ -0,0 +1,84 @@
+//! Packet size distributions, so suites can send a mix of sizes like real
+//! traffic instead of one fixed size per case.
+
+use std::fmt;
+
+/// IP packet sizes and how often each is drawn relative to the others.
+#[derive(Debug, Clone)]
+pub struct SizeMix {
+    entries: Vec<(usize, u32)>,
+    total_weight: u32,
+}
+
+impl SizeMix {
+    /// Creates a mix from `(size, weight)` pairs. Returns `None` if the mix
+    /// is empty or all weights are zero.
+    pub fn new(entries: Vec<(usize, u32)>) -> Option<Self> {
+        let total_weight = entries.iter().map(|&(_, weight)| weight).sum();
+        if total_weight == 0 {
+            return None;
+        }
+        Some(SizeMix {
+            entries: entries,
+            total_weight: total_weight,
+        })
+    }
+
+    /// The simple IMIX, 64, 576 and 1500 byte packets in a 7:4:1 ratio.
+    pub fn imix() -> Self {
+        SizeMix::new(vec![(64, 7), (576, 4), (1500, 1)]).unwrap()
+    }
+
+    /// Mean IP packet size of the mix.
+    pub fn average(&self) -> f64 {
+        let sum = self.entries.iter().map(|&(size, weight)| size * weight as usize).sum::<usize>();
+        sum as f64 / self.total_weight as f64
+    }
+
+    /// Creates a sampler drawing sizes from the mix. Samplers with different
+    /// seeds draw different sequences.
+    pub fn sampler(&self, seed: u64) -> Sampler {
+        Sampler {
+            mix: self.clone(),
+            // Xorshift gets stuck at zero.
+            state: seed.wrapping_mul(0x9e3779b97f4a7c15) | 1,
+        }
+    }
+}
+
+/// Formats the mix as `size:weight` pairs separated by commas, the way it's
+/// given on the command line.
+impl fmt::Display for SizeMix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, &(size, weight)) in self.entries.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}:{}", size, weight)?;
+        }
+        Ok(())
+    }
+}
+
+/// Draws IP packet sizes from a `SizeMix` with a xorshift generator, cheap
+/// enough to not show up next to sending.
+pub struct Sampler {
+    mix: SizeMix,
+    state: u64,
+}
+
+impl Sampler {
+    pub fn draw(&mut self) -> usize {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        let mut point = (self.state % self.mix.total_weight as u64) as u32;
+        for &(size, weight) in &self.mix.entries {
+            if point < weight {
+                return size;
+            }
+            point -= weight;
+        }
+        unreachable!()
+    }
+}
@@ -0,0 +1,135 @@
+//! Registry of all benchmark suites. `Protocol`, used to select suites on
+//! the command line, is generated from it.
+
+use Config;
+use bench;
+use checksum_bench;
+use construct_bench::{EthernetConstructBench, Ipv4ConstructBench, UdpConstructBench};
+use kernel_bench::{self, KernelBench};
+use pnet_bench::{self, PnetBench};
+use progress::Printer;
+use rips::EthernetChannel;
+use rips_bench::{self, EthernetBench, Ipv4Bench, UdpBench};
+use rtt_bench;
+use rx;
+
+use std::io;
+use std::time::Duration;
+
+/// Runs one direction of a suite over a channel, until done or the first
+/// error.
+pub type RunFn = fn(EthernetChannel, &Config, &mut Printer) -> io::Result<()>;
+
+pub struct Suite {
+    pub tx: RunFn,
+    /// Round trip suites measure both directions when sending and have no
+    /// separate receive benchmark.
+    pub rx: Option<RunFn>,
+    /// The receive benchmark reads the channel itself, not through a rips
+    /// stack, and should be given one whose reads time out, so its thread
+    /// can be stopped when the case ends.
+    pub rx_timeout: bool,
+    /// The suite never touches the network and should be given a mock
+    /// channel, whatever the backend.
+    pub discard: bool,
+}
+
+impl Suite {
+    /// Read timeout of the channel given to the receive benchmark, if it
+    /// needs one.
+    pub fn rx_read_timeout(&self) -> Option<Duration> {
+        if self.rx_timeout {
+            Some(Duration::from_millis(rx::READ_TIMEOUT_MS))
+        } else {
+            None
+        }
+    }
+}
+
+macro_rules! registry {
+    ($($protocol:ident => $suite:expr),+) => {
+        arg_enum! {
+            #[derive(Debug, Clone, Copy)]
+            pub enum Protocol {
+                $($protocol),+
+            }
+        }
+
+        impl Protocol {
+            pub fn suite(&self) -> Suite {
+                match *self {
+                    $(Protocol::$protocol => $suite),+
+                }
+            }
+        }
+    }
+}
+
+registry! {
+    Pnet => Suite {
+        tx: bench::run::<PnetBench>,
+        rx: Some(pnet_bench::bench_rx),
+        rx_timeout: true,
+        discard: false,
+    },
+    Ethernet => Suite {
+        tx: bench::run::<EthernetBench>,
+        rx: Some(rips_bench::bench_ethernet_rx),
+        rx_timeout: true,
+        discard: false,
+    },
+    Ipv4 => Suite {
+        tx: bench::run::<Ipv4Bench>,
+        rx: Some(rips_bench::bench_ipv4_rx),
+        rx_timeout: true,
+        discard: false,
+    },
+    Udp => Suite {
+        tx: bench::run::<UdpBench>,
+        rx: Some(rips_bench::bench_udp_rx),
+        rx_timeout: false,
+        discard: false,
+    },
+    Icmp => Suite {
+        tx: rtt_bench::bench_icmp,
+        rx: None,
+        rx_timeout: false,
+        discard: false,
+    },
+    UdpRtt => Suite {
+        tx: rtt_bench::bench_udp_rtt,
+        rx: None,
+        rx_timeout: false,
+        discard: false,
+    },
+    Kernel => Suite {
+        tx: bench::run::<KernelBench>,
+        rx: Some(kernel_bench::bench_rx),
+        rx_timeout: false,
+        discard: false,
+    },
+    ConstructEthernet => Suite {
+        tx: bench::run::<EthernetConstructBench>,
+        rx: None,
+        rx_timeout: false,
+        discard: true,
+    },
+    ConstructIpv4 => Suite {
+        tx: bench::run::<Ipv4ConstructBench>,
+        rx: None,
+        rx_timeout: false,
+        discard: true,
+    },
+    ConstructUdp => Suite {
+        tx: bench::run::<UdpConstructBench>,
+        rx: None,
+        rx_timeout: false,
+        discard: true,
+    },
+    Checksum => Suite {
+        tx: checksum_bench::bench,
+        rx: None,
+        rx_timeout: false,
+        discard: true,
+    }
+}
@@ -0,0 +1,208 @@
+use Config;
+use bench::{self, SendFn};
+use histogram::Histogram;
+use progress::{Meter, Printer, duration_secs};
+use results::JsonObject;
+use rx::{RxCounter, SAMPLE_INTERVAL_MS};
+
+use std::io;
+use std::mem;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// State shared between the sending threads and the thread sampling them.
+struct Shared {
+    /// Cleared when the case is done, or when a thread failed.
+    running: AtomicBool,
+    /// Set while a repetition is measured, after the warmup.
+    measuring: AtomicBool,
+    invalid_tx: AtomicUsize,
+    /// Number of repetitions done. Every thread times its send calls into a
+    /// histogram of its own and hands it over when it sees this move, so the
+    /// send path takes no lock.
+    repetition: AtomicUsize,
+}
+
+/// Runs every sender on its own thread for all repetitions of one case. The
+/// aggregate rate is reported like for a single sender, and the rate of every
+/// thread is added to the record of the case. Returns the senders, for the
+/// next case, and the number of invalidated Tx. If a thread fails, all of
+/// them are stopped and its error is returned.
+pub fn measure(printer: &mut Printer,
+               config: &Config,
+               senders: Vec<SendFn>,
+               packets_per_call: usize,
+               bytes_per_packet: usize)
+               -> io::Result<(Vec<SendFn>, usize)> {
+    let shared = Arc::new(Shared {
+        running: AtomicBool::new(true),
+        measuring: AtomicBool::new(false),
+        invalid_tx: AtomicUsize::new(0),
+        repetition: AtomicUsize::new(0),
+    });
+    let (send_latency_tx, send_latency_rx) = mpsc::channel();
+    let timed = config.send_latency;
+    let counters = senders.iter().map(|_| RxCounter::new()).collect::<Vec<_>>();
+    let handles = senders.into_iter()
+        .zip(counters.iter().cloned())
+        .enumerate()
+        .map(|(i, (mut send, counter))| {
+            let shared = shared.clone();
+            let send_latency_tx = send_latency_tx.clone();
+            let placement = config.placement.clone();
+            thread::spawn(move || {
+                let mut send_latency = Histogram::new();
+                let mut seen_repetition = 0;
+                let result = placement.apply(i).and_then(|()| {
+                    while shared.running.load(Ordering::Relaxed) {
+                        let result = if timed {
+                            let current_repetition = shared.repetition.load(Ordering::Acquire);
+                            if current_repetition != seen_repetition {
+                                let done = mem::replace(&mut send_latency, Histogram::new());
+                                send_latency_tx.send(done).unwrap();
+                                seen_repetition = current_repetition;
+                            }
+                            let start = Instant::now();
+                            let result = send(packets_per_call, bytes_per_packet);
+                            if shared.measuring.load(Ordering::Relaxed) {
+                                send_latency.record(start.elapsed());
+                            }
+                            result
+                        } else {
+                            send(packets_per_call, bytes_per_packet)
+                        };
+                        match result {
+                            Ok(Some((pkgs, bytes))) => counter.add_packets(pkgs, bytes),
+                            Ok(None) => {
+                                shared.invalid_tx.fetch_add(1, Ordering::Relaxed);
+                            }
+                            Err(e) => {
+                                return Err(bench::context(e,
+                                                          "Error while sending to the network"))
+                            }
+                        }
+                    }
+                    Ok(())
+                });
+                match result {
+                    Ok(()) => Ok(send),
+                    Err(e) => {
+                        // Stops the other threads and the case.
+                        shared.running.store(false, Ordering::Relaxed);
+                        Err(e)
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    // Only the threads hold senders now, so receiving ends once they exit.
+    drop(send_latency_tx);
+
+    let sampled = sample(printer, config, &shared, &counters, timed, &send_latency_rx);
+    shared.running.store(false, Ordering::Relaxed);
+    let senders = handles.into_iter()
+        .map(|handle| handle.join().unwrap())
+        .collect::<io::Result<Vec<_>>>()?;
+    let (thread_totals, total_secs) = sampled?;
+
+    let mut json = JsonObject::new();
+    for (i, &(pkgs, bytes)) in thread_totals.iter().enumerate() {
+        let pps = pkgs as f64 / total_secs;
+        printer.print_message(&format!("Thread {}: {:.0} pps", i, pps));
+        json = json.object(&i.to_string(),
+                           JsonObject::new()
+                               .number("packets", pkgs)
+                               .number("bytes", bytes)
+                               .number("pps", pps));
+    }
+    printer.add_case_details("threads", json);
+    Ok((senders, shared.invalid_tx.load(Ordering::Relaxed)))
+}
+
+/// Samples the counters of the threads for all repetitions, until they are
+/// done or a thread failed. Returns the packets and bytes every thread sent
+/// while measuring, and the seconds measured.
+fn sample(printer: &mut Printer,
+          config: &Config,
+          shared: &Shared,
+          counters: &[RxCounter],
+          timed: bool,
+          send_latencies: &Receiver<Histogram>)
+          -> io::Result<(Vec<(usize, usize)>, f64)> {
+    let mut thread_totals = vec![(0, 0); counters.len()];
+    let mut total_secs = 0.0;
+    let mut last_invalid_tx = 0;
+    for _ in 0..config.repeat {
+        let mut meter = Meter::new(config);
+        let (mut last_pkgs, mut last_bytes) = sum(counters);
+        let mut start = None;
+        while shared.running.load(Ordering::Relaxed) && meter.tick(printer)? {
+            if start.is_none() && !meter.warming_up() {
+                let start_counts = counters.iter().map(|c| c.get()).collect::<Vec<_>>();
+                start = Some((Instant::now(), start_counts));
+                shared.measuring.store(true, Ordering::Relaxed);
+            }
+            thread::sleep(Duration::from_millis(SAMPLE_INTERVAL_MS));
+            let (pkgs, bytes) = sum(counters);
+            meter.add(pkgs - last_pkgs, bytes - last_bytes);
+            last_pkgs = pkgs;
+            last_bytes = bytes;
+        }
+        shared.measuring.store(false, Ordering::Relaxed);
+        if !shared.running.load(Ordering::Relaxed) {
+            break;
+        }
+        if timed {
+            shared.repetition.fetch_add(1, Ordering::Release);
+            let send_latency = match collect_send_latency(shared, counters.len(), send_latencies) {
+                Some(send_latency) => send_latency,
+                None => break,
+            };
+            if !send_latency.is_empty() {
+                printer.set_send_latency(send_latency);
+            }
+        }
+        let total_invalid_tx = shared.invalid_tx.load(Ordering::Relaxed);
+        printer.set_invalid_tx(total_invalid_tx - last_invalid_tx);
+        last_invalid_tx = total_invalid_tx;
+        if let Some((start_time, start_counts)) = start {
+            total_secs += duration_secs(start_time.elapsed());
+            for (i, counter) in counters.iter().enumerate() {
+                let (pkgs, bytes) = counter.get();
+                let (start_pkgs, start_bytes) = start_counts[i];
+                thread_totals[i].0 += pkgs - start_pkgs;
+                thread_totals[i].1 += bytes - start_bytes;
+            }
+        }
+        printer.end_repetition();
+    }
+    Ok((thread_totals, total_secs))
+}
+
+/// Merges the histograms every thread hands over when it sees a repetition
+/// end. Returns `None` if a thread failed before handing over its own.
+fn collect_send_latency(shared: &Shared,
+                        threads: usize,
+                        send_latencies: &Receiver<Histogram>)
+                        -> Option<Histogram> {
+    let mut send_latency = Histogram::new();
+    let mut received = 0;
+    while received < threads {
+        match send_latencies.recv_timeout(Duration::from_millis(SAMPLE_INTERVAL_MS)) {
+            Ok(thread_send_latency) => {
+                send_latency.merge(&thread_send_latency);
+                received += 1;
+            }
+            Err(RecvTimeoutError::Timeout) if shared.running.load(Ordering::Relaxed) => (),
+            Err(_) => return None,
+        }
+    }
+    Some(send_latency)
+}
+
+fn sum(counters: &[RxCounter]) -> (usize, usize) {
+    counters.iter().map(|c| c.get()).fold((0, 0), |(pkgs, bytes), (p, b)| (pkgs + p, bytes + b))
+}
@@ -0,0 +1,104 @@
+//! Accounting of the bytes a benchmark puts on the wire, headers and
+//! Ethernet framing included, as opposed to the payload it hands the stack.
+
+const ETHERNET_HEADER: usize = 14;
+/// Shortest frame allowed, excluding the frame check sequence.
+const ETHERNET_MIN_FRAME: usize = 60;
+/// Frame check sequence, preamble with start of frame delimiter and the
+/// inter-frame gap.
+const ETHERNET_FRAMING: usize = 4 + 8 + 12;
+const IPV4_HEADER: usize = 20;
+/// UDP and ICMP echo headers are both eight bytes.
+const TRANSPORT_HEADER: usize = 8;
+
+/// Smallest MTU every IPv4 link has to support, see RFC 791. Leaves room for
+/// the headers and a fragment of at least eight bytes.
+pub const MIN_MTU: usize = 68;
+
+/// What the payload bytes counted by a suite are the payload of.
+#[derive(Debug, Clone, Copy)]
+pub enum Encapsulation {
+    /// Whole Ethernet frames, headers included.
+    Frame,
+    Ethernet,
+    Ipv4,
+    /// UDP datagrams or ICMP echo messages.
+    Udp,
+}
+
+impl Encapsulation {
+    /// Bytes on the wire for `packets` packets with `bytes` payload bytes in
+    /// total. Exact when all packets are the same size, an approximation of
+    /// padding and fragmentation otherwise.
+    pub fn wire_bytes(&self, packets: usize, bytes: usize, mtu: usize) -> usize {
+        if packets == 0 {
+            return 0;
+        }
+        packets * self.packet_wire_bytes(bytes / packets, mtu)
+    }
+
+    fn packet_wire_bytes(&self, payload: usize, mtu: usize) -> usize {
+        match *self {
+            Encapsulation::Frame => frame_wire_bytes(payload),
+            Encapsulation::Ethernet => frame_wire_bytes(ETHERNET_HEADER + payload),
+            Encapsulation::Ipv4 => ipv4_wire_bytes(payload, mtu),
+            Encapsulation::Udp => ipv4_wire_bytes(TRANSPORT_HEADER + payload, mtu),
+        }
+    }
+}
+
+fn frame_wire_bytes(frame: usize) -> usize {
+    frame.max(ETHERNET_MIN_FRAME) + ETHERNET_FRAMING
+}
+
+/// Bytes on the wire for an IPv4 packet, fragmented to fit `mtu`, which is at
+/// least `MIN_MTU`.
+fn ipv4_wire_bytes(ip_payload: usize, mtu: usize) -> usize {
+    let fragment_frame = |fragment| frame_wire_bytes(ETHERNET_HEADER + IPV4_HEADER + fragment);
+    if ip_payload + IPV4_HEADER <= mtu {
+        return fragment_frame(ip_payload);
+    }
+    // All fragments but the last carry a multiple of eight bytes.
+    let max_fragment = (mtu - IPV4_HEADER) / 8 * 8;
+    let full_fragments = (ip_payload - 1) / max_fragment;
+    let last_fragment = ip_payload - full_fragments * max_fragment;
+    full_fragments * fragment_frame(max_fragment) + fragment_frame(last_fragment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Encapsulation, ipv4_wire_bytes};
+
+    #[test]
+    fn pads_short_frames() {
+        assert_eq!(Encapsulation::Frame.wire_bytes(1, 42, 1500), 60 + 24);
+        assert_eq!(Encapsulation::Ethernet.wire_bytes(1, 46, 1500), 60 + 24);
+        assert_eq!(Encapsulation::Udp.wire_bytes(1, 0, 1500), 60 + 24);
+        assert_eq!(Encapsulation::Udp.wire_bytes(0, 0, 1500), 0);
+    }
+
+    #[test]
+    fn fills_the_mtu() {
+        assert_eq!(Encapsulation::Ipv4.wire_bytes(1, 1480, 1500), 1514 + 24);
+        assert_eq!(Encapsulation::Udp.wire_bytes(10, 14720, 1500), 10 * (1514 + 24));
+    }
+
+    #[test]
+    fn fragments() {
+        // One byte over the MTU, carried by a padded second fragment.
+        assert_eq!(ipv4_wire_bytes(1481, 1500), 1514 + 24 + 60 + 24);
+        // The largest size sent, 43 full fragments of 1480 bytes and 1368 left.
+        let full_fragment = 14 + 20 + 1480 + 24;
+        let last_fragment = 14 + 20 + 1368 + 24;
+        assert_eq!(ipv4_wire_bytes(65008, 1500), 43 * full_fragment + last_fragment);
+        assert_eq!(Encapsulation::Udp.wire_bytes(1, 65000, 1500),
+                   43 * full_fragment + last_fragment);
+    }
+
+    #[test]
+    fn fragments_at_the_smallest_mtu() {
+        // 48 bytes per fragment, 100 bytes take three.
+        let full_fragment = 14 + 20 + 48 + 24;
+        assert_eq!(ipv4_wire_bytes(100, 68), 2 * full_fragment + 60 + 24);
+    }
+}
@@ -0,0 +1,27 @@
+extern crate ripsbench;
+
+use ripsbench::{Config, Protocol, args, mock};
+use std::time::Duration;
+
+#[test]
+fn udp_suite_over_mock_channel() {
+    let mut config = Config::new();
+    config.duration = Duration::from_millis(200);
+    config.output = args::Output::Quiet;
+    config.backend = args::Backend::Mock;
+    let (channel, handle) = mock::channel(65535, None, &config);
+
+    let records = ripsbench::run_tx(Protocol::Udp, channel, &config).unwrap();
+
+    // The default config has a single size, filling the MTU.
+    assert_eq!(records.len(), 1);
+    let record = &records[0];
+    assert_eq!(record.suite, "Rips UDP sending");
+    assert_eq!(record.packets_per_call, 1);
+    assert_eq!(record.bytes_per_packet, 1500 - 20 - 8);
+    assert!(record.packets > 0);
+    assert_eq!(record.bytes, record.packets * record.bytes_per_packet);
+    assert!(record.send_latency.is_none());
+    // Every datagram went out on the channel, after the ARP request.
+    assert!(handle.sent().0 > record.packets);
+}